@@ -0,0 +1,158 @@
+use std::fmt;
+use std::io;
+use crate::tiles::Tile;
+use crate::game::*;
+use crate::pieces::*;
+
+/// A map and the piece falling on it, written as text so tests and tools can set up positions
+/// and compare them without playing the pieces in. Saves and puzzles write their maps this way.
+///
+/// Each line is a row of the map, the last one being at the bottom and rows above the first one
+/// being empty. `.` is an empty tile, `G` a garbage tile, a piece letter a tile set by that kind
+/// of piece and `#` a tile set by an unknown piece. The tiles of the falling piece are written
+/// with its letter in lowercase:
+///
+/// ```text
+/// ....t.....
+/// ...ttt....
+/// ZZ....IIII
+/// GGGGG.GGGG
+/// ```
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Board {
+    pub map: Map,
+    pub falling_piece: Option<Piece>,
+}
+
+impl Board {
+    /// The map of a game and its falling piece, if one is falling.
+    pub fn from_state(state: &GameState) -> Board {
+        Board {
+            map: state.map.clone(),
+            falling_piece: Some(state.falling_piece.clone()).filter(|_| state.phase == Phase::Falling),
+        }
+    }
+
+    /// Reads a board on a map of the given size. Leading whitespace and blank lines are ignored,
+    /// so boards can be indented in the source. When the falling piece covers the same tiles in
+    /// more than one rotation, it gets the first of them.
+    pub fn parse(text: &str, width: u16, height: u16, buffer_height: u16) -> io::Result<Board> {
+        let rows: Vec<&str> = text.lines()
+            .map(str::trim)
+            .filter(|row| !row.is_empty())
+            .collect();
+        if rows.len() > (height + buffer_height) as usize {
+            return Err(invalid_data("board is too tall"));
+        }
+
+        let mut map = Map::new(width, height, buffer_height);
+        let mut falling_tiles = Vec::new();
+        let mut falling_kind = None;
+        let first_row = height as i16 - rows.len() as i16;
+        for (y, row) in (first_row..).zip(&rows) {
+            if row.chars().count() != width as usize {
+                return Err(invalid_data(&format!("board rows must be {} tiles wide", width)));
+            }
+
+            for (x, c) in row.chars().enumerate() {
+                let tile = Tile::new(x as i16, y);
+                let map_tile = &mut map[tile];
+                match c {
+                    '.' => {}
+                    '#' => map_tile.is_set = true,
+                    'G' => {
+                        map_tile.is_set = true;
+                        map_tile.is_garbage = true;
+                    }
+                    _ => {
+                        let kind = PieceKind::from_char(c).ok_or_else(|| invalid_data(&format!("invalid tile {}", c)))?;
+                        if c.is_ascii_uppercase() {
+                            map_tile.is_set = true;
+                            map_tile.kind = Some(kind);
+                        } else if falling_kind.is_none_or(|falling_kind| falling_kind == kind) {
+                            falling_kind = Some(kind);
+                            falling_tiles.push(tile);
+                        } else {
+                            return Err(invalid_data("falling piece tiles must all be of the same kind"));
+                        }
+                    }
+                }
+            }
+        }
+
+        let falling_piece = match falling_kind {
+            Some(kind) => Some(find_piece(kind, &falling_tiles, width, buffer_height)
+                .ok_or_else(|| invalid_data(&format!("falling tiles don't form a {} piece", kind.to_char())))?),
+            None => None,
+        };
+
+        Ok(Board { map, falling_piece })
+    }
+
+    /// Reads a board on a map of the size given by `settings`.
+    pub fn parse_for(text: &str, settings: &GameSettings) -> io::Result<Board> {
+        Board::parse(text, settings.width, settings.height, settings.buffer_height)
+    }
+}
+
+/// Writes the rows from the highest one with a set tile or a falling piece tile down to the
+/// bottom of the map, so an empty board is written as nothing.
+impl fmt::Display for Board {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let map = &self.map;
+        let falling_tiles = self.falling_piece.as_ref().map_or(&[][..], |piece| &piece.tiles[..]);
+        let top_y = (-(map.buffer_height as i16)..map.height as i16)
+            .find(|&y| (0..map.width as i16).any(|x| {
+                let tile = Tile::new(x, y);
+                map[tile].is_set || falling_tiles.contains(&tile)
+            }))
+            .unwrap_or(map.height as i16);
+
+        for y in top_y..map.height as i16 {
+            let row: String = (0..map.width as i16)
+                .map(|x| {
+                    let tile = Tile::new(x, y);
+                    match (&self.falling_piece, map[tile]) {
+                        (Some(piece), _) if piece.tiles.contains(&tile) => piece.kind.to_char().to_ascii_lowercase(),
+                        (_, MapTile { is_garbage: true, .. }) => 'G',
+                        (_, MapTile { kind: Some(kind), .. }) => kind.to_char(),
+                        (_, MapTile { is_set: true, .. }) => '#',
+                        _ => '.',
+                    }
+                })
+                .collect();
+            writeln!(f, "{}", row)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The piece of the given kind that covers exactly `tiles`, in the first rotation that does.
+pub(crate) fn find_piece(kind: PieceKind, tiles: &[Tile], width: u16, buffer_height: u16) -> Option<Piece> {
+    (0..4).find_map(|rotation_index| piece_covering(kind, rotation_index, tiles, width, buffer_height))
+}
+
+/// The piece of the given kind and rotation that covers exactly `tiles`, turned and moved from
+/// its spawn position like a falling piece would be.
+pub(crate) fn piece_covering(kind: PieceKind, rotation_index: usize, tiles: &[Tile], width: u16, buffer_height: u16) -> Option<Piece> {
+    let mut piece = spawn_piece_of_type(kind.piece_type(), width, buffer_height);
+    for _ in 0..rotation_index {
+        rotate_clockwise(&mut piece);
+    }
+
+    let mut tiles = tiles.to_vec();
+    tiles.sort_by_key(|tile| (tile.y, tile.x));
+    let mut piece_tiles = piece.tiles.clone();
+    piece_tiles.sort_by_key(|tile| (tile.y, tile.x));
+    let delta = *tiles.first()? - *piece_tiles.first()?;
+    if piece_tiles.len() != tiles.len() || piece_tiles.iter().zip(&tiles).any(|(&tile, &target)| tile + delta != target) {
+        return None;
+    }
+
+    for tile in &mut piece.tiles {
+        *tile += delta;
+    }
+    piece.origin += delta;
+    Some(piece)
+}
@@ -0,0 +1,96 @@
+use std::io;
+use crate::game::*;
+use crate::pieces::*;
+use crate::input::*;
+use crate::time::*;
+use crate::drawing::*;
+
+/// Builds a `Game`, starting from defaults that need no type annotations: no input, random
+/// pieces, the system clock and no drawing. Each component can be replaced independently.
+pub struct GameBuilder<I = QueuedInput, PTS = RandomPieceTypeSelector, TC = StdClock, TD = NoopDrawing> {
+    input: I,
+    piece_type_selector: PTS,
+    clock: TC,
+    drawing: TD,
+    settings: GameSettings,
+}
+
+impl GameBuilder {
+    pub fn new() -> GameBuilder {
+        GameBuilder {
+            input: QueuedInput::default(),
+            piece_type_selector: RandomPieceTypeSelector {},
+            clock: StdClock {},
+            drawing: NoopDrawing {},
+            settings: GameSettings::default(),
+        }
+    }
+}
+
+impl Default for GameBuilder {
+    fn default() -> GameBuilder {
+        GameBuilder::new()
+    }
+}
+
+impl<I: InputSource, PTS: PieceTypeSelector, TC: Clock, TD: Drawing> GameBuilder<I, PTS, TC, TD> {
+    pub fn input<TI: InputSource>(self, input: TI) -> GameBuilder<TI, PTS, TC, TD> {
+        GameBuilder {
+            input,
+            piece_type_selector: self.piece_type_selector,
+            clock: self.clock,
+            drawing: self.drawing,
+            settings: self.settings,
+        }
+    }
+
+    pub fn piece_type_selector<TPTS: PieceTypeSelector>(self, piece_type_selector: TPTS) -> GameBuilder<I, TPTS, TC, TD> {
+        GameBuilder {
+            input: self.input,
+            piece_type_selector,
+            clock: self.clock,
+            drawing: self.drawing,
+            settings: self.settings,
+        }
+    }
+
+    pub fn clock<TTC: Clock>(self, clock: TTC) -> GameBuilder<I, PTS, TTC, TD> {
+        GameBuilder {
+            input: self.input,
+            piece_type_selector: self.piece_type_selector,
+            clock,
+            drawing: self.drawing,
+            settings: self.settings,
+        }
+    }
+
+    pub fn drawing<TTD: Drawing>(self, drawing: TTD) -> GameBuilder<I, PTS, TC, TTD> {
+        GameBuilder {
+            input: self.input,
+            piece_type_selector: self.piece_type_selector,
+            clock: self.clock,
+            drawing,
+            settings: self.settings,
+        }
+    }
+
+    pub fn settings(mut self, settings: GameSettings) -> Self {
+        self.settings = settings;
+        self
+    }
+
+    pub fn width(mut self, width: u16) -> Self {
+        self.settings.width = width;
+        self
+    }
+
+    pub fn height(mut self, height: u16) -> Self {
+        self.settings.height = height;
+        self
+    }
+
+    /// Builds the game, or fails if the settings aren't valid.
+    pub fn build(self) -> io::Result<Game<I, PTS, TC, TD>> {
+        Game::new(self.input, self.piece_type_selector, self.clock, self.drawing, self.settings)
+    }
+}
@@ -0,0 +1,46 @@
+use crate::tiles::*;
+use crate::pieces::PieceKind;
+use crate::game::TopOut;
+use crate::finesse::FinesseFault;
+
+/// Something that happened in a game, emitted by `run_frame` as it happens. There is no `Hold`
+/// event because the engine has no hold yet; it is deferred until hold is added.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum GameEvent {
+    PieceSpawned { kind: PieceKind },
+    PieceMoved { delta: Tile },
+    /// `kick` is the index of the wall kick test that made the rotation fit, or `None` if the
+    /// piece fit without being kicked.
+    Rotated { clockwise: bool, kick: Option<usize> },
+    Locked { kind: PieceKind, tiles: Vec<Tile> },
+    /// Emitted when a piece locks and completes lines, before any line clear delay. T-spins that
+    /// clear no lines are reported with a `count` of zero.
+    LinesCleared { count: usize, tspin: TSpin },
+    LevelUp { level: u32 },
+    /// Garbage rows were pushed up from the bottom of the map.
+    GarbageAdded { rows: usize },
+    TopOut(TopOut),
+    /// In practice mode, the stack was cleared instead of topping out.
+    StackCleared,
+    /// In finesse mode, a piece locked after more presses than needed.
+    FinesseFault(FinesseFault),
+    /// The goal of the game mode was reached, ending the game.
+    GoalReached,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TSpin {
+    None,
+    Mini,
+    Full,
+}
+
+pub trait EventListener {
+    fn on_event(&mut self, event: &GameEvent);
+}
+
+impl<F> EventListener for F where F: FnMut(&GameEvent) {
+    fn on_event(&mut self, event: &GameEvent) {
+        self(event)
+    }
+}
@@ -0,0 +1,135 @@
+use std::collections::{HashMap, VecDeque};
+use std::io;
+use crate::tiles::*;
+use crate::pieces::*;
+use crate::game::*;
+use crate::modes::GameMode;
+use crate::time::ManualClock;
+use crate::input::QueuedInput;
+use crate::drawing::NoopDrawing;
+use crate::builder::GameBuilder;
+
+type TableGame = Game<QueuedInput, ManualPieceTypeSelector, ManualClock, NoopDrawing>;
+
+/// Fewest moves and rotations needed to bring each kind of piece from its spawn position to each
+/// column and orientation, on an empty map of a given size. Placements are told apart by the
+/// tiles they cover, so rotations of symmetric pieces that cover the same tiles share an entry.
+#[derive(Clone, Debug)]
+pub struct FinesseTable {
+    presses: HashMap<(PieceKind, Vec<Tile>), u32>,
+}
+
+impl FinesseTable {
+    pub fn new(width: u16, buffer_height: u16) -> io::Result<FinesseTable> {
+        let mut presses = HashMap::new();
+        for piece_index in 0..ALL_PIECES.len() {
+            let game = GameBuilder::new()
+                .piece_type_selector(ManualPieceTypeSelector { piece_index })
+                .clock(ManualClock { now_milliseconds: 0 })
+                .settings(GameSettings { width, buffer_height, mode: GameMode::Zen, ..GameSettings::default() })
+                .build()?;
+            add_reachable_placements(game, &mut presses);
+        }
+
+        Ok(FinesseTable { presses })
+    }
+
+    /// Fewest presses that place a piece like `piece`, whatever its height.
+    pub fn minimal_presses(&self, piece: &Piece) -> Option<u32> {
+        self.presses.get(&(piece.kind, placement_key(&piece.tiles))).copied()
+    }
+}
+
+/// Breadth-first search over the positions the falling piece of `game` reaches with single
+/// presses, which never move it down.
+fn add_reachable_placements(game: TableGame, presses: &mut HashMap<(PieceKind, Vec<Tile>), u32>) {
+    let kind = game.state.falling_piece.kind;
+    let mut visited = vec![(game.state.falling_piece.origin, game.state.falling_piece.rotation_index)];
+    let mut queue = VecDeque::new();
+    queue.push_back((game, 0));
+
+    while let Some((game, distance)) = queue.pop_front() {
+        presses.entry((kind, placement_key(&game.state.falling_piece.tiles))).or_insert(distance);
+
+        let moves: [fn(&mut TableGame); 4] = [
+            TableGame::move_left,
+            TableGame::move_right,
+            TableGame::try_rotate_clockwise,
+            TableGame::try_rotate_counterclockwise,
+        ];
+        for apply_move in &moves {
+            let mut next = game.clone();
+            apply_move(&mut next);
+            let piece = &next.state.falling_piece;
+            let position = (piece.origin, piece.rotation_index);
+            if !visited.contains(&position) {
+                visited.push(position);
+                queue.push_back((next, distance + 1));
+            }
+        }
+    }
+}
+
+/// The tiles of a placement, moved up so the highest one is on row zero.
+fn placement_key(tiles: &[Tile]) -> Vec<Tile> {
+    let top_y = tiles.iter().map(|tile| tile.y).min().unwrap_or(0);
+    let mut key: Vec<Tile> = tiles.iter().map(|tile| Tile::new(tile.x, tile.y - top_y)).collect();
+    key.sort_by_key(|tile| (tile.y, tile.x));
+    key
+}
+
+/// A placement that took more presses than needed.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct FinesseFault {
+    pub kind: PieceKind,
+    pub presses: u32,
+    pub minimal_presses: u32,
+}
+
+/// Counts the presses spent on each piece and compares them with the finesse table when it locks.
+#[derive(Clone, Debug)]
+pub struct FinesseTrainer {
+    pub table: FinesseTable,
+    /// Moves and rotations pressed since the falling piece spawned.
+    pub presses: u32,
+    pub placements: u32,
+    pub faults: u32,
+    /// The fault of the last placement, if it had one.
+    pub last_fault: Option<FinesseFault>,
+}
+
+impl FinesseTrainer {
+    pub fn new(settings: &GameSettings) -> io::Result<FinesseTrainer> {
+        Ok(FinesseTrainer {
+            table: FinesseTable::new(settings.width, settings.buffer_height)?,
+            presses: 0,
+            placements: 0,
+            faults: 0,
+            last_fault: None,
+        })
+    }
+
+    /// Checks the presses spent on a piece that just locked and starts counting for the next one.
+    /// The table ignores the stack, so tucks and spins that need extra presses are faults too.
+    pub fn check_placement(&mut self, piece: &Piece) -> Option<FinesseFault> {
+        let presses = self.presses;
+        self.presses = 0;
+        self.placements += 1;
+        self.last_fault = self.table.minimal_presses(piece)
+            .filter(|minimal_presses| presses > *minimal_presses)
+            .map(|minimal_presses| FinesseFault { kind: piece.kind, presses, minimal_presses });
+        if self.last_fault.is_some() {
+            self.faults += 1;
+        }
+
+        self.last_fault
+    }
+
+    /// Share of placements that were faults, from 0 to 1.
+    pub fn fault_rate(&self) -> f64 {
+        match self.placements {
+            0 => 0.0,
+            placements => self.faults as f64 / placements as f64,
+        }
+    }
+}
@@ -0,0 +1,578 @@
+use std::io;
+use crate::tiles::Tile;
+use crate::game::*;
+use crate::pieces::*;
+use crate::board::{Board, find_piece, piece_covering};
+use crate::events::GameEvent;
+use crate::replay::Replay;
+use crate::playback::Playback;
+
+const FUMEN_PREFIX: &str = "v115@";
+const ENCODE_TABLE: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+const COMMENT_TABLE: &[u8] = b" !\"#$%&'()*+,-./0123456789:;<=>?@ABCDEFGHIJKLMNOPQRSTUVWXYZ[\\]^_`abcdefghijklmnopqrstuvwxyz{|}~";
+const COMMENT_BASE: u32 = COMMENT_TABLE.len() as u32 + 1;
+const MAX_COMMENT_LENGTH: usize = 4095;
+/// Fumen fields are always this wide.
+pub const FUMEN_WIDTH: u16 = 10;
+/// Rows of a fumen field, not counting the garbage row below them.
+const FIELD_HEIGHT: usize = 23;
+const FIELD_BLOCKS: usize = (FIELD_HEIGHT + 1) * FUMEN_WIDTH as usize;
+const GRAY: u8 = 8;
+
+/// Tiles of a fumen field, row by row from the top and ending with the garbage row, holding `0`
+/// for empty tiles, the number of the piece that set the others or `GRAY`.
+type Field = [u8; FIELD_BLOCKS];
+
+/// A page of a fumen diagram: a map with the piece placed on it.
+///
+/// Fumen fields are 10 tiles wide and 23 rows tall, and are laid out at the bottom of maps.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct FumenPage {
+    pub board: Board,
+    pub comment: String,
+    /// Whether the piece locks into the map of the next page, clearing lines.
+    pub lock: bool,
+}
+
+impl FumenPage {
+    pub fn from_state(state: &GameState) -> FumenPage {
+        FumenPage {
+            board: Board::from_state(state),
+            comment: String::new(),
+            lock: true,
+        }
+    }
+}
+
+/// A piece of a fumen page, with its tiles as `(x, y)` pairs counted from the bottom left of the
+/// field.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+struct FumenPiece {
+    kind: PieceKind,
+    rotation_index: usize,
+    tiles: [(i16, i16); 4],
+}
+
+#[derive(Clone, Debug)]
+struct RawPage {
+    field: Field,
+    piece: Option<FumenPiece>,
+    comment: String,
+    lock: bool,
+}
+
+/// Reads the pages of a fumen, with or without the address of a fumen site before it, on maps of
+/// the given height.
+pub fn decode_fumen(fumen: &str, height: u16, buffer_height: u16) -> io::Result<Vec<FumenPage>> {
+    let start = fumen.find(FUMEN_PREFIX).ok_or_else(|| invalid_data("not a v115 fumen"))?;
+    let mut values = Values::parse(&fumen[start + FUMEN_PREFIX.len()..])?;
+    let mut field = [0; FIELD_BLOCKS];
+    let mut repeat_count = 0;
+    let mut comment = String::new();
+    let mut pages = Vec::new();
+
+    while !values.is_empty() {
+        if repeat_count > 0 {
+            repeat_count -= 1;
+        } else {
+            let mut index = 0;
+            while index < FIELD_BLOCKS {
+                let run = values.poll(2)? as usize;
+                let diff = run / FIELD_BLOCKS;
+                let count = run % FIELD_BLOCKS + 1;
+                if diff == GRAY as usize && count == FIELD_BLOCKS {
+                    repeat_count = values.poll(1)?;
+                }
+                if index + count > FIELD_BLOCKS {
+                    return Err(invalid_data("field is too large"));
+                }
+
+                for block in &mut field[index..index + count] {
+                    let value = (*block as usize + diff).checked_sub(GRAY as usize).filter(|value| *value <= GRAY as usize);
+                    *block = value.ok_or_else(|| invalid_data("invalid field"))? as u8;
+                }
+                index += count;
+            }
+        }
+
+        let action = values.poll(3)? as usize;
+        let rotation_index = decode_rotation(action / 8 % 4);
+        let location = action / 32 % FIELD_BLOCKS;
+        let flags = action / 32 / FIELD_BLOCKS;
+        let piece = match piece_kind(action % 8) {
+            Some(_) if location >= FIELD_HEIGHT * FUMEN_WIDTH as usize => {
+                return Err(invalid_data("piece is below the field"));
+            }
+            kind => kind.map(|kind| FumenPiece::decode(kind, rotation_index, location)),
+        };
+
+        if flags & 8 != 0 {
+            let length = values.poll(2)? as usize;
+            let mut escaped = String::new();
+            for _ in 0..length.div_ceil(4) {
+                let mut value = values.poll(5)?;
+                for _ in 0..4 {
+                    let c = COMMENT_TABLE.get((value % COMMENT_BASE) as usize).ok_or_else(|| invalid_data("invalid comment"))?;
+                    escaped.push(*c as char);
+                    value /= COMMENT_BASE;
+                }
+            }
+            escaped.truncate(length);
+            comment = unescape(&escaped);
+        }
+
+        let lock = flags & 16 == 0;
+        pages.push(RawPage { field, piece, comment: comment.clone(), lock });
+        if lock {
+            field = next_field(field, piece, flags & 1 != 0, flags & 2 != 0);
+        }
+    }
+
+    pages.iter()
+        .map(|page| page.to_page(height, buffer_height))
+        .collect()
+}
+
+/// Writes pages as a fumen. Their maps must be 10 tiles wide, with nothing above the top of the
+/// fumen field.
+pub fn encode_fumen(pages: &[FumenPage]) -> io::Result<String> {
+    let pages = pages.iter()
+        .map(RawPage::from_page)
+        .collect::<io::Result<Vec<_>>>()?;
+    encode_pages(&pages)
+}
+
+/// Writes a replay as a fumen with a page for each piece that locked, on the map it locked on,
+/// and a last page with the map and falling piece the replay ends with. Garbage that rises in
+/// the same frame as a piece locks, but before it, only shows on the following page.
+pub fn replay_to_fumen(replay: &Replay) -> io::Result<String> {
+    let settings = replay.settings;
+    if settings.width != FUMEN_WIDTH {
+        return Err(invalid_data("fumen maps must be 10 tiles wide"));
+    }
+
+    let mut playback = Playback::new(replay.clone())?;
+    let mut field = map_to_field(&playback.state().map)?;
+    let mut pages = Vec::new();
+    while !playback.is_finished() {
+        for event in playback.next_frame() {
+            if let GameEvent::Locked { kind, tiles } = event {
+                let piece = find_piece(*kind, tiles, settings.width, settings.buffer_height)
+                    .ok_or_else(|| invalid_data("locked tiles don't form a piece"))?;
+                let piece = Some(FumenPiece::from_piece(&piece, settings.height)?);
+                pages.push(RawPage { field, piece, comment: String::new(), lock: true });
+                field = next_field(field, piece, false, false);
+            }
+        }
+
+        field = clear_lines(map_to_field(&playback.state().map)?);
+    }
+
+    pages.push(RawPage::from_page(&FumenPage::from_state(playback.state()))?);
+    encode_pages(&pages)
+}
+
+fn encode_pages(pages: &[RawPage]) -> io::Result<String> {
+    let mut values = Values::default();
+    let mut previous_field = [0; FIELD_BLOCKS];
+    let mut previous_comment = "";
+    let mut repeat_index = None;
+
+    for (page_index, page) in pages.iter().enumerate() {
+        let runs = field_runs(&previous_field, &page.field);
+        let is_unchanged = runs == [(GRAY as usize + 1) * FIELD_BLOCKS - 1];
+        match repeat_index {
+            Some(index) if is_unchanged && values.digits[index] < ENCODE_TABLE.len() as u32 - 1 => values.digits[index] += 1,
+            _ => {
+                for run in runs {
+                    values.push(run as u32, 2);
+                }
+                repeat_index = None;
+                if is_unchanged {
+                    values.push(0, 1);
+                    repeat_index = Some(values.digits.len() - 1);
+                }
+            }
+        }
+
+        let comment_changed = page.comment != previous_comment;
+        let (kind, rotation, location) = match page.piece {
+            Some(piece) => (piece_value(piece.kind), encode_rotation(piece.rotation_index), piece.location()?),
+            None => (0, 0, 0),
+        };
+        let flags = if page_index == 0 { 4 } else { 0 }
+            + if comment_changed { 8 } else { 0 }
+            + if page.lock { 0 } else { 16 };
+        values.push((kind + 8 * rotation + 32 * (location + FIELD_BLOCKS * flags)) as u32, 3);
+
+        if comment_changed {
+            let escaped = escape(&page.comment);
+            if escaped.len() > MAX_COMMENT_LENGTH {
+                return Err(invalid_data("comment is too long"));
+            }
+
+            values.push(escaped.len() as u32, 2);
+            for chunk in escaped.as_bytes().chunks(4) {
+                let value = chunk.iter().rev().fold(0, |value, c| {
+                    let index = COMMENT_TABLE.iter().position(|table_c| table_c == c).unwrap_or(0);
+                    value * COMMENT_BASE + index as u32
+                });
+                values.push(value, 5);
+            }
+        }
+
+        previous_comment = &page.comment;
+        previous_field = if page.lock { next_field(page.field, page.piece, false, false) } else { page.field };
+    }
+
+    Ok(format!("{}{}", FUMEN_PREFIX, values))
+}
+
+/// The field encoded as runs of tiles that changed by the same amount since `previous`.
+fn field_runs(previous: &Field, field: &Field) -> Vec<usize> {
+    let diffs: Vec<usize> = previous.iter().zip(field.iter())
+        .map(|(&previous, &block)| block as usize + GRAY as usize - previous as usize)
+        .collect();
+
+    let mut runs = Vec::new();
+    let mut start = 0;
+    for index in 1..=FIELD_BLOCKS {
+        if index == FIELD_BLOCKS || diffs[index] != diffs[start] {
+            runs.push(diffs[start] * FIELD_BLOCKS + index - start - 1);
+            start = index;
+        }
+    }
+
+    runs
+}
+
+/// The field of the page after one with `field` and `piece` that locks.
+fn next_field(mut field: Field, piece: Option<FumenPiece>, rise: bool, mirror: bool) -> Field {
+    if let Some(piece) = piece {
+        for &(x, y) in &piece.tiles {
+            let row = FIELD_HEIGHT as i16 - 1 - y;
+            if (0..FUMEN_WIDTH as i16).contains(&x) && (0..FIELD_HEIGHT as i16).contains(&row) {
+                field[row as usize * FUMEN_WIDTH as usize + x as usize] = piece_value(piece.kind) as u8;
+            }
+        }
+    }
+
+    field = clear_lines(field);
+    let width = FUMEN_WIDTH as usize;
+    if rise {
+        field.copy_within(width.., 0);
+        field[FIELD_BLOCKS - width..].fill(0);
+    }
+    if mirror {
+        for row in field[..FIELD_HEIGHT * width].chunks_mut(width) {
+            row.reverse();
+        }
+    }
+
+    field
+}
+
+/// Removes the full rows of the field, moving the rows above them down.
+fn clear_lines(field: Field) -> Field {
+    let width = FUMEN_WIDTH as usize;
+    let rows: Vec<&[u8]> = field[..FIELD_HEIGHT * width].chunks(width)
+        .filter(|row| row.contains(&0))
+        .collect();
+
+    let mut cleared = [0; FIELD_BLOCKS];
+    let first_row = FIELD_HEIGHT - rows.len();
+    for (index, row) in rows.iter().enumerate() {
+        cleared[(first_row + index) * width..(first_row + index + 1) * width].copy_from_slice(row);
+    }
+    cleared[FIELD_HEIGHT * width..].copy_from_slice(&field[FIELD_HEIGHT * width..]);
+    cleared
+}
+
+fn map_to_field(map: &Map) -> io::Result<Field> {
+    if map.width != FUMEN_WIDTH {
+        return Err(invalid_data("fumen maps must be 10 tiles wide"));
+    }
+
+    let mut field = [0; FIELD_BLOCKS];
+    for y in -(map.buffer_height as i16)..map.height as i16 {
+        for x in 0..map.width as i16 {
+            let map_tile = map[Tile::new(x, y)];
+            if !map_tile.is_set {
+                continue;
+            }
+
+            let row = field_row(y, map.height).ok_or_else(|| invalid_data("map is too tall for a fumen field"))?;
+            field[row * FUMEN_WIDTH as usize + x as usize] = match map_tile {
+                MapTile { is_garbage: false, kind: Some(kind), .. } => piece_value(kind) as u8,
+                _ => GRAY,
+            };
+        }
+    }
+
+    Ok(field)
+}
+
+fn field_to_map(field: &Field, height: u16, buffer_height: u16) -> io::Result<Map> {
+    let mut map = Map::new(FUMEN_WIDTH, height, buffer_height);
+    for (index, &value) in field[..FIELD_HEIGHT * FUMEN_WIDTH as usize].iter().enumerate() {
+        if value == 0 {
+            continue;
+        }
+
+        let tile = Tile::new((index % FUMEN_WIDTH as usize) as i16, map_y(index / FUMEN_WIDTH as usize, height));
+        if !map.contains(tile) {
+            return Err(invalid_data("fumen field doesn't fit in the map"));
+        }
+
+        let map_tile = &mut map[tile];
+        map_tile.is_set = true;
+        map_tile.is_garbage = value == GRAY;
+        map_tile.kind = piece_kind(value as usize);
+    }
+
+    Ok(map)
+}
+
+/// Row of the fumen field, counted from the top, that holds row `y` of a map of the given height.
+fn field_row(y: i16, height: u16) -> Option<usize> {
+    let row = y + FIELD_HEIGHT as i16 - height as i16;
+    (0..FIELD_HEIGHT as i16).contains(&row).then_some(row as usize)
+}
+
+fn map_y(row: usize, height: u16) -> i16 {
+    row as i16 + height as i16 - FIELD_HEIGHT as i16
+}
+
+impl RawPage {
+    fn from_page(page: &FumenPage) -> io::Result<RawPage> {
+        let map = &page.board.map;
+        Ok(RawPage {
+            field: map_to_field(map)?,
+            piece: page.board.falling_piece.as_ref()
+                .map(|piece| FumenPiece::from_piece(piece, map.height))
+                .transpose()?,
+            comment: page.comment.clone(),
+            lock: page.lock,
+        })
+    }
+
+    fn to_page(&self, height: u16, buffer_height: u16) -> io::Result<FumenPage> {
+        let falling_piece = match self.piece {
+            Some(piece) => {
+                let tiles: Vec<Tile> = piece.tiles.iter()
+                    .map(|&(x, y)| Tile::new(x, map_y(FIELD_HEIGHT - 1, height) - y))
+                    .collect();
+                if tiles.iter().any(|tile| !(0..FUMEN_WIDTH as i16).contains(&tile.x) || tile.y < -(buffer_height as i16) || tile.y >= height as i16) {
+                    return Err(invalid_data("piece doesn't fit in the map"));
+                }
+                piece_covering(piece.kind, piece.rotation_index, &tiles, FUMEN_WIDTH, buffer_height)
+            }
+            None => None,
+        };
+
+        Ok(FumenPage {
+            board: Board { map: field_to_map(&self.field, height, buffer_height)?, falling_piece },
+            comment: self.comment.clone(),
+            lock: self.lock,
+        })
+    }
+}
+
+impl FumenPiece {
+    /// Tiles of each kind of piece in its spawn rotation, around the tile it is placed by.
+    fn spawn_tiles(kind: PieceKind) -> [(i16, i16); 4] {
+        match kind {
+            PieceKind::I => [(0, 0), (-1, 0), (1, 0), (2, 0)],
+            PieceKind::J => [(0, 0), (-1, 0), (1, 0), (-1, 1)],
+            PieceKind::L => [(0, 0), (-1, 0), (1, 0), (1, 1)],
+            PieceKind::O => [(0, 0), (1, 0), (0, 1), (1, 1)],
+            PieceKind::S => [(0, 0), (-1, 0), (0, 1), (1, 1)],
+            PieceKind::Z => [(0, 0), (1, 0), (0, 1), (-1, 1)],
+            PieceKind::T => [(0, 0), (-1, 0), (1, 0), (0, 1)],
+        }
+    }
+
+    fn rotated_tiles(kind: PieceKind, rotation_index: usize) -> [(i16, i16); 4] {
+        FumenPiece::spawn_tiles(kind).map(|(x, y)| match rotation_index {
+            1 => (y, -x),
+            2 => (-x, -y),
+            3 => (-y, x),
+            _ => (x, y),
+        })
+    }
+
+    /// How far the tile a piece is placed by is from the position fumen stores for it, which
+    /// is off by one for some rotations of the O, I, S and Z pieces.
+    fn location_offset(kind: PieceKind, rotation_index: usize) -> (i16, i16) {
+        match (kind, rotation_index) {
+            (PieceKind::O, 0) | (PieceKind::S, 0) | (PieceKind::Z, 0) | (PieceKind::I, 3) => (0, -1),
+            (PieceKind::O, 2) | (PieceKind::I, 2) | (PieceKind::Z, 3) => (1, 0),
+            (PieceKind::O, 3) => (1, -1),
+            (PieceKind::S, 1) => (-1, 0),
+            _ => (0, 0),
+        }
+    }
+
+    fn decode(kind: PieceKind, rotation_index: usize, location: usize) -> FumenPiece {
+        let (offset_x, offset_y) = FumenPiece::location_offset(kind, rotation_index);
+        let x = (location % FUMEN_WIDTH as usize) as i16 + offset_x;
+        let y = (FIELD_HEIGHT - 1 - location / FUMEN_WIDTH as usize) as i16 + offset_y;
+        FumenPiece {
+            kind,
+            rotation_index,
+            tiles: FumenPiece::rotated_tiles(kind, rotation_index).map(|(tile_x, tile_y)| (x + tile_x, y + tile_y)),
+        }
+    }
+
+    fn from_piece(piece: &Piece, height: u16) -> io::Result<FumenPiece> {
+        let bottom_y = map_y(FIELD_HEIGHT - 1, height);
+        let mut tiles = [(0, 0); 4];
+        if piece.tiles.len() != tiles.len() {
+            return Err(invalid_data("pieces must have four tiles"));
+        }
+        for (tile, piece_tile) in tiles.iter_mut().zip(&piece.tiles) {
+            *tile = (piece_tile.x, bottom_y - piece_tile.y);
+        }
+
+        Ok(FumenPiece { kind: piece.kind, rotation_index: piece.rotation_index, tiles })
+    }
+
+    fn location(&self) -> io::Result<usize> {
+        let mut rotated_tiles = FumenPiece::rotated_tiles(self.kind, self.rotation_index);
+        let mut tiles = self.tiles;
+        rotated_tiles.sort();
+        tiles.sort();
+        let (x, y) = (tiles[0].0 - rotated_tiles[0].0, tiles[0].1 - rotated_tiles[0].1);
+        if rotated_tiles.iter().zip(&tiles).any(|(&(tile_x, tile_y), &tile)| (x + tile_x, y + tile_y) != tile) {
+            return Err(invalid_data("piece tiles don't match its rotation"));
+        }
+
+        let (offset_x, offset_y) = FumenPiece::location_offset(self.kind, self.rotation_index);
+        let (x, y) = (x - offset_x, y - offset_y);
+        if !(0..FUMEN_WIDTH as i16).contains(&x) || !(0..FIELD_HEIGHT as i16).contains(&y) {
+            return Err(invalid_data("piece is outside the fumen field"));
+        }
+
+        Ok((FIELD_HEIGHT - 1 - y as usize) * FUMEN_WIDTH as usize + x as usize)
+    }
+}
+
+fn piece_value(kind: PieceKind) -> usize {
+    match kind {
+        PieceKind::I => 1,
+        PieceKind::L => 2,
+        PieceKind::O => 3,
+        PieceKind::Z => 4,
+        PieceKind::T => 5,
+        PieceKind::J => 6,
+        PieceKind::S => 7,
+    }
+}
+
+fn piece_kind(value: usize) -> Option<PieceKind> {
+    match value {
+        1 => Some(PieceKind::I),
+        2 => Some(PieceKind::L),
+        3 => Some(PieceKind::O),
+        4 => Some(PieceKind::Z),
+        5 => Some(PieceKind::T),
+        6 => Some(PieceKind::J),
+        7 => Some(PieceKind::S),
+        _ => None,
+    }
+}
+
+/// Fumen numbers rotations counterclockwise from the upside down one, so the same conversion
+/// goes both ways.
+fn decode_rotation(value: usize) -> usize {
+    (6 - value) % 4
+}
+
+fn encode_rotation(rotation_index: usize) -> usize {
+    decode_rotation(rotation_index)
+}
+
+/// Comments are stored escaped like JavaScript's `escape` does.
+fn escape(text: &str) -> String {
+    let mut escaped = String::new();
+    for unit in text.encode_utf16() {
+        match char::from_u32(unit as u32) {
+            Some(c) if c.is_ascii_alphanumeric() || "@*_+-./".contains(c) => escaped.push(c),
+            _ if unit < 0x100 => escaped.push_str(&format!("%{:02X}", unit)),
+            _ => escaped.push_str(&format!("%u{:04X}", unit)),
+        }
+    }
+
+    escaped
+}
+
+fn unescape(escaped: &str) -> String {
+    let mut units = Vec::new();
+    let mut rest = escaped;
+    while let Some(c) = rest.chars().next() {
+        let unit = match (rest.strip_prefix("%u"), rest.strip_prefix('%')) {
+            (Some(hex), _) if hex.get(..4).is_some_and(is_hex) => Some((u16::from_str_radix(&hex[..4], 16), 6)),
+            (_, Some(hex)) if hex.get(..2).is_some_and(is_hex) => Some((u16::from_str_radix(&hex[..2], 16), 3)),
+            _ => None,
+        };
+        match unit {
+            Some((Ok(unit), length)) => {
+                units.push(unit);
+                rest = &rest[length..];
+            }
+            _ => {
+                units.extend(c.encode_utf16(&mut [0; 2]).iter());
+                rest = &rest[c.len_utf8()..];
+            }
+        }
+    }
+
+    String::from_utf16_lossy(&units)
+}
+
+fn is_hex(text: &str) -> bool {
+    text.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// Base 64 digits of a fumen, each number written with its least significant digit first.
+#[derive(Default)]
+struct Values {
+    digits: Vec<u32>,
+    next_index: usize,
+}
+
+impl Values {
+    fn parse(data: &str) -> io::Result<Values> {
+        let digits = data.bytes()
+            .filter(|&c| c != b'?')
+            .map(|c| ENCODE_TABLE.iter().position(|&table_c| table_c == c)
+                .map(|digit| digit as u32)
+                .ok_or_else(|| invalid_data(&format!("invalid fumen character {}", c as char))))
+            .collect::<io::Result<_>>()?;
+        Ok(Values { digits, next_index: 0 })
+    }
+
+    fn is_empty(&self) -> bool {
+        self.next_index >= self.digits.len()
+    }
+
+    fn poll(&mut self, count: usize) -> io::Result<u32> {
+        let digits = self.digits.get(self.next_index..self.next_index + count)
+            .ok_or_else(|| invalid_data("unexpected end of fumen"))?;
+        self.next_index += count;
+        Ok(digits.iter().rev().fold(0, |value, digit| value * ENCODE_TABLE.len() as u32 + digit))
+    }
+
+    fn push(&mut self, mut value: u32, count: usize) {
+        for _ in 0..count {
+            self.digits.push(value % ENCODE_TABLE.len() as u32);
+            value /= ENCODE_TABLE.len() as u32;
+        }
+    }
+}
+
+impl std::fmt::Display for Values {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        self.digits.iter().try_for_each(|&digit| write!(f, "{}", ENCODE_TABLE[digit as usize] as char))
+    }
+}
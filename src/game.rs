@@ -2,47 +2,1064 @@ use crate::tiles::*;
 use crate::pieces::*;
 use crate::input::*;
 use crate::time::*;
-use std::ops::Index;
-use crate::drawing::Drawing;
+use std::io;
+use std::mem;
+use std::ops::{Index, IndexMut};
+use crate::drawing::{Drawing, NoopDrawing};
+use crate::events::*;
+use crate::modes::*;
+use crate::scoring::*;
+use crate::garbage::*;
+use crate::finesse::FinesseTrainer;
+use crate::replay::{Replay, RecordedInput};
 
-pub const WIDTH: u16 = 10;
-pub const HEIGHT: u16 = 20;
+pub const DEFAULT_WIDTH: u16 = 10;
+pub const DEFAULT_HEIGHT: u16 = 20;
+pub const DEFAULT_BUFFER_HEIGHT: u16 = 20;
+/// New pieces spawn this many rows above the visible playfield, or as high as the buffer allows.
+pub const SPAWN_ROWS_ABOVE_VISIBLE: u16 = 2;
+pub const DEFAULT_LINE_CLEAR_DELAY_MILLIS: u128 = 400;
+pub const DEFAULT_SPAWN_DELAY_MILLIS: u128 = 100;
+/// Cells of a big game are this many tiles wide and tall.
+pub const BIG_SCALE: u16 = 2;
+/// Maps must fit the widest piece, the I piece lying flat.
+pub const MIN_WIDTH: u16 = 4;
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub struct MapTile {
     pub tile: Tile,
     pub is_set: bool,
+    /// Whether the tile belongs to a garbage row rather than to a locked piece.
+    pub is_garbage: bool,
+    /// Kind of the piece that set the tile, if it was set by one.
+    pub kind: Option<PieceKind>,
+    /// Game time at which the tile was last set, for drawings that fade the stack out.
+    pub set_millis: u128,
 }
 
-pub struct Game<TInput: InputSource, TPTS: PieceTypeSelector, TCI: ClockInstant, TC: Clock<TCI>, TD: Drawing> {
+/// A running game. Use `GameBuilder` to create one without spelling out every type parameter.
+pub struct Game<
+    TInput: InputSource = QueuedInput,
+    TPTS: PieceTypeSelector = RandomPieceTypeSelector,
+    TC: Clock = StdClock,
+    TD: Drawing = NoopDrawing> {
     pub state: GameState,
     pub clock: TC,
-    pub last_move_instant: TCI,
-    pub ended: bool,
+    pub start_instant: TC::Instant,
+    /// Game time that had already elapsed when `start_instant` was taken, e.g. in a resumed game.
+    pub time_offset_millis: u128,
+    pub top_out: Option<TopOut>,
+    /// Whether the goal of the game mode was reached, which also ends the game.
+    pub goal_reached: bool,
+    /// Whether the last successful movement of the falling piece was a rotation, and which kick
+    /// it used. Used to detect T-spins.
+    pub last_rotation_kick: Option<Option<usize>>,
+    /// Events emitted since the start of the current frame.
+    pub events: Vec<GameEvent>,
+    pub listeners: Vec<Box<dyn EventListener>>,
+    /// Replay being recorded, with every input read so far.
+    pub recording: Option<Replay>,
+    /// States to go back to when undoing in practice mode, taken each time a piece locked, with
+    /// that piece back at its spawn position. The last one is the most recent. The piece type
+    /// selector isn't part of it, see `undo`.
+    pub undo_history: Vec<GameState>,
+    /// States left by undoing, to go back to when redoing.
+    pub redo_history: Vec<GameState>,
+    /// Checks every placement in finesse mode.
+    pub finesse: Option<FinesseTrainer>,
     pub input: TInput,
     pub piece_type_selector: TPTS,
-    pub drawing: TD
+    pub drawing: TD,
+    pub settings: GameSettings
 }
 
+/// Cloning a game copies all of its state, but listeners stay subscribed only to the original.
+impl<TInput, TPTS, TC, TD> Clone for Game<TInput, TPTS, TC, TD> where
+    TInput: InputSource + Clone,
+    TPTS: PieceTypeSelector + Clone,
+    TC: Clock + Clone,
+    TC::Instant: Clone,
+    TD: Drawing + Clone {
+    fn clone(&self) -> Self {
+        Game {
+            state: self.state.clone(),
+            clock: self.clock.clone(),
+            start_instant: self.start_instant.clone(),
+            time_offset_millis: self.time_offset_millis,
+            top_out: self.top_out,
+            goal_reached: self.goal_reached,
+            last_rotation_kick: self.last_rotation_kick,
+            events: self.events.clone(),
+            listeners: Vec::new(),
+            recording: self.recording.clone(),
+            undo_history: self.undo_history.clone(),
+            redo_history: self.redo_history.clone(),
+            finesse: self.finesse.clone(),
+            input: self.input.clone(),
+            piece_type_selector: self.piece_type_selector.clone(),
+            drawing: self.drawing.clone(),
+            settings: self.settings,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct GameSettings {
+    pub width: u16,
+    pub height: u16,
+    pub buffer_height: u16,
+    /// Ends the game when a piece locks with any of its tiles above the visible playfield,
+    /// instead of only when all of them are.
+    pub partial_lock_out: bool,
+    /// How long cleared lines stay on the playfield before collapsing.
+    pub line_clear_delay_millis: u128,
+    /// How long it takes for the next piece to spawn after the previous one locked (ARE).
+    pub spawn_delay_millis: u128,
+    pub mode: GameMode,
+    /// Seed for the position of the holes in garbage rows.
+    pub garbage_seed: u64,
+    /// Plays with blocks of `BIG_SCALE` by `BIG_SCALE` tiles. The map is made of whole blocks, so
+    /// its size is given in blocks, and every row of blocks cleared counts as `BIG_SCALE` lines.
+    pub big: bool,
+}
+
+impl GameSettings {
+    /// Checks that a game can be played with these settings: the map is at least `MIN_WIDTH`
+    /// tiles wide, every kind of piece fits at its spawn position and every tile has coordinates
+    /// that fit in an `i16`.
+    pub fn validate(&self) -> io::Result<()> {
+        if self.width < MIN_WIDTH {
+            return Err(invalid_data(&format!("the map must be at least {} tiles wide", MIN_WIDTH)));
+        }
+        if self.width > i16::MAX as u16 || self.height as u32 + self.buffer_height as u32 > i16::MAX as u32 {
+            return Err(invalid_data("the map is too big"));
+        }
+
+        let fits_spawns = ALL_PIECES.iter()
+            .flat_map(|piece_type| spawn_piece_of_type(piece_type, self.width, self.buffer_height).tiles)
+            .all(|tile| tile.y >= -(self.buffer_height as i16) && tile.y < self.height as i16);
+        if !fits_spawns {
+            return Err(invalid_data("the map doesn't have enough rows for pieces to spawn"));
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for GameSettings {
+    fn default() -> GameSettings {
+        GameSettings {
+            width: DEFAULT_WIDTH,
+            height: DEFAULT_HEIGHT,
+            buffer_height: DEFAULT_BUFFER_HEIGHT,
+            partial_lock_out: false,
+            line_clear_delay_millis: DEFAULT_LINE_CLEAR_DELAY_MILLIS,
+            spawn_delay_millis: DEFAULT_SPAWN_DELAY_MILLIS,
+            mode: GameMode::Endless,
+            garbage_seed: 0,
+            big: false,
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
 pub struct GameState {
     pub falling_piece: Piece,
-    pub map: Map
+    pub map: Map,
+    pub phase: Phase,
+    /// Game time up to which the game has been simulated, in milliseconds since it started.
+    pub time_millis: u128,
+    /// Game time when the falling piece last moved down or spawned.
+    pub last_move_millis: u128,
+    /// Game time when the current phase started.
+    pub phase_start_millis: u128,
+    pub lines_cleared: u32,
+    pub level: u32,
+    pub score: u64,
+    /// Game time at which every `SPLIT_LINES` lines were cleared.
+    pub splits_millis: Vec<u128>,
+    /// Lines cleared that contained garbage.
+    pub garbage_lines_cleared: u32,
+    /// State of the generator for the holes in garbage rows.
+    pub garbage_rng_state: u64,
+    /// Garbage rows pushed up from the bottom during the game.
+    pub garbage_rows_added: u32,
+    /// Game time when the last garbage row was pushed up, or when the game started.
+    pub last_garbage_millis: u128,
+    /// Kind of the next piece to spawn, if the player chose it in practice mode.
+    pub next_piece_kind: Option<PieceKind>,
+    pub pieces_locked: u32,
+    /// Line clears that left the map empty.
+    pub perfect_clears: u32,
+    /// T-spins that cleared two lines.
+    pub tspin_doubles: u32,
 }
 
+/// What the game is doing between frames. `falling_piece` is only in play while `Falling`; during
+/// the other phases it still holds the piece that has just locked.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Phase {
+    Falling,
+    ClearingLines { lines: Vec<i16>, elapsed_millis: u128, duration_millis: u128 },
+    Spawning,
+}
+
+/// The playfield. Visible rows go from `0` to `height - 1`, while the hidden buffer rows above
+/// them use negative `y` values down to `-buffer_height`. `tiles` is indexed by column and then
+/// by row, with the topmost buffer row stored first.
+#[derive(Clone, PartialEq, Eq, Debug)]
 pub struct Map {
-    pub tiles: [[MapTile; HEIGHT as usize]; WIDTH as usize],
+    pub width: u16,
+    pub height: u16,
+    pub buffer_height: u16,
+    pub tiles: Vec<Vec<MapTile>>,
+}
+
+impl Map {
+    pub fn new(width: u16, height: u16, buffer_height: u16) -> Map {
+        let tiles = (0..width as i16)
+            .map(|x| (-(buffer_height as i16)..height as i16)
+                .map(|y| MapTile {
+                    tile: Tile::new(x, y),
+                    is_set: false,
+                    is_garbage: false,
+                    kind: None,
+                    set_millis: 0,
+                })
+                .collect())
+            .collect();
+
+        Map {
+            width,
+            height,
+            buffer_height,
+            tiles,
+        }
+    }
+
+    pub fn contains(&self, tile: Tile) -> bool {
+        tile.x >= 0
+            && tile.x < self.width as i16
+            && tile.y >= -(self.buffer_height as i16)
+            && tile.y < self.height as i16
+    }
+
+    pub fn row_index(&self, y: i16) -> usize {
+        (y + self.buffer_height as i16) as usize
+    }
+
+    /// Fills row `y` with the given tiles at game time `set_millis`, `true` marking the filled ones.
+    pub fn set_row(&mut self, y: i16, row: &[bool], is_garbage: bool, set_millis: u128) {
+        for (x, is_set) in row.iter().enumerate() {
+            let map_tile = &mut self[Tile::new(x as i16, y)];
+            map_tile.is_set = *is_set;
+            map_tile.is_garbage = is_garbage && *is_set;
+            map_tile.kind = None;
+            map_tile.set_millis = if *is_set { set_millis } else { 0 };
+        }
+    }
+
+    /// Moves every row up by the number of `rows` and fills the bottom with them as garbage, the
+    /// last one being the lowest. Returns whether any set tile was pushed out of the top. When
+    /// there are more rows than the map holds, only the last ones stay and the others are pushed
+    /// out too.
+    pub fn insert_rows_at_bottom(&mut self, rows: &[Vec<bool>], set_millis: u128) -> bool {
+        let total_rows = (self.buffer_height + self.height) as usize;
+        let (dropped_rows, rows) = rows.split_at(rows.len().saturating_sub(total_rows));
+        let count = rows.len();
+        let pushed_out = dropped_rows.iter().any(|row| row.contains(&true))
+            || self.tiles.iter().any(|column| column[..count].iter().any(|map_tile| map_tile.is_set));
+        for column in &mut self.tiles {
+            for i in 0..column.len() - count {
+                column[i].is_set = column[i + count].is_set;
+                column[i].is_garbage = column[i + count].is_garbage;
+                column[i].kind = column[i + count].kind;
+                column[i].set_millis = column[i + count].set_millis;
+            }
+        }
+
+        let first_row = self.height as i16 - count as i16;
+        for (y, row) in (first_row..).zip(rows) {
+            self.set_row(y, row, true, set_millis);
+        }
+
+        pushed_out
+    }
+
+    pub fn is_garbage_row(&self, y: i16) -> bool {
+        (0..self.width as i16).any(|x| self[Tile::new(x, y)].is_garbage)
+    }
 }
 
 impl Index<Tile> for Map {
     type Output = MapTile;
     fn index(&self, tile: Tile) -> &MapTile {
-        &self.tiles[tile.x as usize][tile.y as usize]
+        &self.tiles[tile.x as usize][self.row_index(tile.y)]
+    }
+}
+
+impl IndexMut<Tile> for Map {
+    fn index_mut(&mut self, tile: Tile) -> &mut MapTile {
+        let row_index = self.row_index(tile.y);
+        &mut self.tiles[tile.x as usize][row_index]
     }
 }
 
 #[derive(PartialEq, Eq, Debug)]
 pub enum FrameResult {
     GameInProgress,
-    PlayerLost,
+    PlayerLost(TopOut),
+    GoalReached,
     GameQuitRequested
-}
\ No newline at end of file
+}
+
+/// The condition that ended a game, named after the guideline top-out rules.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[allow(clippy::enum_variant_names)]
+pub enum TopOut {
+    /// A newly spawned piece overlaps the stack.
+    BlockOut,
+    /// A piece locked entirely above the visible playfield.
+    LockOut,
+    /// A piece locked partially above the visible playfield, with `partial_lock_out` enabled.
+    PartialLockOut,
+    /// Rising garbage pushed the stack, or the falling piece, out of the top of the map.
+    GarbageOut,
+    /// A puzzle used all of its pieces without reaching its goal.
+    OutOfPieces,
+}
+
+impl<I: InputSource, PTS: PieceTypeSelector, TC: Clock, TD: Drawing> Game<I, PTS, TC, TD> {
+    /// Starts a game with its first piece spawned, or fails if `settings` aren't valid.
+    pub fn new(input: I, mut piece_type_selector: PTS, clock: TC, drawing: TD, settings: GameSettings) -> io::Result<Game<I, PTS, TC, TD>> {
+        settings.validate()?;
+        let falling_piece = Self::create_piece(&mut piece_type_selector, &settings);
+        let mut garbage_rng_state = settings.garbage_seed;
+        let garbage_rows: Vec<Vec<bool>> = (0..settings.mode.initial_garbage_rows(settings.height))
+            .map(|_| garbage_row(settings.width, &mut garbage_rng_state))
+            .collect();
+
+        let mut game = Game {
+            events: vec![GameEvent::PieceSpawned { kind: falling_piece.kind }],
+            state: GameState {
+                map: Self::initialize_map(&settings, &garbage_rows),
+                falling_piece,
+                phase: Phase::Falling,
+                time_millis: 0,
+                last_move_millis: 0,
+                phase_start_millis: 0,
+                lines_cleared: 0,
+                level: 1,
+                score: 0,
+                splits_millis: Vec::new(),
+                garbage_lines_cleared: 0,
+                garbage_rng_state,
+                garbage_rows_added: 0,
+                last_garbage_millis: 0,
+                next_piece_kind: None,
+                pieces_locked: 0,
+                perfect_clears: 0,
+                tspin_doubles: 0,
+            },
+            start_instant: clock.now(),
+            time_offset_millis: 0,
+            clock,
+            top_out: None,
+            goal_reached: false,
+            last_rotation_kick: None,
+            listeners: Vec::new(),
+            recording: None,
+            undo_history: Vec::new(),
+            redo_history: Vec::new(),
+            finesse: Self::create_finesse_trainer(&settings)?,
+            input,
+            piece_type_selector,
+            drawing,
+            settings
+        };
+        game.check_block_out();
+        Ok(game)
+    }
+    
+    pub(crate) fn create_finesse_trainer(settings: &GameSettings) -> io::Result<Option<FinesseTrainer>> {
+        match settings.mode {
+            GameMode::Finesse => Ok(Some(FinesseTrainer::new(settings)?)),
+            _ => Ok(None),
+        }
+    }
+
+    pub fn play_until_finished(&mut self) -> FrameResult {
+        loop {
+            match self.run_frame() {
+                FrameResult::GameInProgress => continue,
+                a => return a
+            }
+        }
+    }
+
+    /// Registers a listener that is notified of every event as soon as it is emitted.
+    pub fn subscribe(&mut self, listener: Box<dyn EventListener>) {
+        self.listeners.push(listener);
+    }
+
+    fn emit(&mut self, event: GameEvent) {
+        for listener in &mut self.listeners {
+            listener.on_event(&event);
+        }
+
+        self.events.push(event);
+    }
+
+    fn end_game(&mut self, top_out: TopOut) {
+        self.top_out = Some(top_out);
+        self.emit(GameEvent::TopOut(top_out));
+    }
+
+    pub fn is_over(&self) -> bool {
+        self.top_out.is_some() || self.goal_reached
+    }
+
+    /// Current game time, in milliseconds since the game started.
+    pub fn now_millis(&self) -> u128 {
+        self.time_offset_millis + self.start_instant.difference_millis(&self.clock.now())
+    }
+
+    /// Runs everything that was scheduled up to the current time, such as gravity and the end of
+    /// delays, and then applies the pending input. Scheduled updates happen at the exact game
+    /// time they are due instead of at the time of the frame that notices them, so the outcome
+    /// only depends on the inputs and the times they were read, not on the frame rate.
+    pub fn run_frame(&mut self) -> FrameResult {
+        self.events.clear();
+        self.advance_to(self.now_millis());
+
+        if self.read_input() {
+            return FrameResult::GameQuitRequested;
+        }
+
+        if let Some(top_out) = self.top_out {
+            return FrameResult::PlayerLost(top_out);
+        }
+
+        if self.goal_reached {
+            return FrameResult::GoalReached;
+        }
+
+        self.drawing.draw(&self.state);
+        FrameResult::GameInProgress
+    }
+
+    /// Creates an empty map with `garbage_rows` at the bottom, the last one being the lowest.
+    fn initialize_map(settings: &GameSettings, garbage_rows: &[Vec<bool>]) -> Map {
+        let mut map = Map::new(settings.width, settings.height, settings.buffer_height);
+        let first_row = settings.height as i16 - garbage_rows.len() as i16;
+        for (y, row) in (first_row..).zip(garbage_rows) {
+            map.set_row(y, row, true, 0);
+        }
+
+        map
+    }
+
+    fn read_input(&mut self) -> bool {
+        loop {
+            let input_read = self.input.read_input();
+            if let (Some(input), Some(recording)) = (input_read, self.recording.as_mut()) {
+                recording.inputs.push(RecordedInput { time_millis: self.state.time_millis, input });
+            }
+
+            match input_read.as_ref() {
+                Some(InputResult::ExitGame) => return true,
+                Some(_) if self.is_over() => continue,
+                Some(InputResult::Undo) => self.undo(),
+                Some(InputResult::Redo) => self.redo(),
+                Some(InputResult::ChooseNextPiece(kind)) => {
+                    if self.settings.mode.is_practice() {
+                        self.state.next_piece_kind = Some(*kind);
+                    }
+                }
+                Some(_) if self.state.phase != Phase::Falling => continue,
+                Some(input) => {
+                    let is_finesse_press = matches!(input, InputResult::MoveLeft | InputResult::MoveRight
+                        | InputResult::RotateClockwise | InputResult::RotateCounterClockwise);
+                    if let (Some(trainer), true) = (&mut self.finesse, is_finesse_press) {
+                        trainer.presses += 1;
+                    }
+
+                    match input {
+                        InputResult::MoveLeft => self.move_left(),
+                        InputResult::MoveRight => self.move_right(),
+                        InputResult::MoveDown => self.soft_drop(),
+                        InputResult::RotateClockwise => self.try_rotate_clockwise(),
+                        InputResult::RotateCounterClockwise => self.try_rotate_counterclockwise(),
+                        InputResult::ExitGame => return true,
+                        InputResult::Undo | InputResult::Redo | InputResult::ChooseNextPiece(_) => {}
+                    }
+
+                    // Under 20G, a piece moved over a gap drops right away.
+                    self.advance_to(self.state.time_millis);
+                }
+                None => return false
+            }
+        }
+    }
+
+    pub(crate) fn move_left(&mut self) {
+        if can_move_left(&mut self.state) {
+            self.move_piece(Tile::new(-1, 0));
+        }
+    }
+
+    pub(crate) fn move_right(&mut self) {
+        if can_move_right(&mut self.state) {
+            self.move_piece(Tile::new(1, 0));
+        }
+    }
+
+    fn advance_to(&mut self, time_millis: u128) {
+        while !self.is_over() {
+            let due_millis = self.next_update_millis();
+            if due_millis > time_millis {
+                break;
+            }
+
+            self.state.time_millis = self.state.time_millis.max(due_millis);
+            self.run_scheduled_update();
+        }
+
+        // A finished game keeps the time it ended at.
+        if !self.is_over() {
+            self.state.time_millis = self.state.time_millis.max(time_millis);
+        }
+
+        if let Phase::ClearingLines { elapsed_millis, .. } = &mut self.state.phase {
+            *elapsed_millis = self.state.time_millis - self.state.phase_start_millis;
+        }
+    }
+
+    fn next_update_millis(&self) -> u128 {
+        let phase_update_millis = match self.state.phase {
+            Phase::Falling if self.settings.mode.is_practice() => u128::MAX,
+            Phase::Falling => match self.lock_delay_millis() {
+                Some(lock_delay_millis) if !self.can_move_down() => self.state.last_move_millis + lock_delay_millis,
+                _ => self.state.last_move_millis + self.gravity_interval_millis(),
+            },
+            Phase::ClearingLines { duration_millis, .. } => self.state.phase_start_millis + duration_millis,
+            Phase::Spawning => self.state.phase_start_millis + self.spawn_delay_millis(),
+        };
+
+        [self.settings.mode.time_limit_millis(), self.next_garbage_millis()]
+            .iter()
+            .flatten()
+            .fold(phase_update_millis, |due_millis, millis| due_millis.min(*millis))
+    }
+
+    fn next_garbage_millis(&self) -> Option<u128> {
+        self.settings.mode
+            .garbage_interval_millis(self.state.garbage_rows_added)
+            .map(|interval_millis| self.state.last_garbage_millis + interval_millis)
+    }
+
+    /// Time between two gravity steps, following the level unless the mode keeps it constant.
+    /// Zero drops pieces to the floor instantly.
+    pub fn gravity_interval_millis(&self) -> u128 {
+        if let Some(timing) = self.settings.mode.section_timing(self.state.level) {
+            return timing.gravity_interval_millis;
+        }
+
+        match self.settings.mode.has_level_gravity() {
+            true => gravity_interval_millis(self.state.level),
+            false => gravity_interval_millis(1),
+        }
+    }
+
+    /// How long a piece rests on the stack before locking. Without one, pieces lock on the first
+    /// gravity step they cannot take.
+    pub fn lock_delay_millis(&self) -> Option<u128> {
+        self.settings.mode.section_timing(self.state.level).map(|timing| timing.lock_delay_millis)
+    }
+
+    pub fn spawn_delay_millis(&self) -> u128 {
+        self.settings.mode.section_timing(self.state.level)
+            .map_or(self.settings.spawn_delay_millis, |timing| timing.spawn_delay_millis)
+    }
+
+    pub fn line_clear_delay_millis(&self) -> u128 {
+        self.settings.mode.section_timing(self.state.level)
+            .map_or(self.settings.line_clear_delay_millis, |timing| timing.line_clear_delay_millis)
+    }
+
+    fn run_scheduled_update(&mut self) {
+        if matches!(self.settings.mode.time_limit_millis(), Some(limit) if self.state.time_millis >= limit) {
+            self.reach_goal();
+            return;
+        }
+
+        if matches!(self.next_garbage_millis(), Some(due_millis) if self.state.time_millis >= due_millis) {
+            let row = garbage_row(self.settings.width, &mut self.state.garbage_rng_state);
+            self.state.garbage_rows_added += 1;
+            self.state.last_garbage_millis = self.state.time_millis;
+            self.insert_garbage_rows(&[row]);
+            return;
+        }
+
+        match &mut self.state.phase {
+            Phase::Falling => self.fall_piece(),
+            Phase::ClearingLines { lines, .. } => {
+                let lines = std::mem::take(lines);
+                self.clear_lines(&lines);
+                self.begin_spawn_delay();
+            }
+            Phase::Spawning => self.spawn_piece(),
+        }
+    }
+
+    fn soft_drop(&mut self) {
+        if self.can_move_down() {
+            self.state.score += SOFT_DROP_POINTS;
+        }
+
+        self.fall_piece();
+    }
+
+    pub(crate) fn fall_piece(&mut self) {
+        if !self.can_move_down() {
+            if self.settings.mode.is_practice() {
+                self.push_undo_state();
+            }
+
+            for tile in &mut self.state.falling_piece.tiles {
+                self.state.map[*tile].is_set = true;
+                self.state.map[*tile].kind = Some(self.state.falling_piece.kind);
+                self.state.map[*tile].set_millis = self.state.time_millis;
+            }
+
+            self.emit(GameEvent::Locked {
+                kind: self.state.falling_piece.kind,
+                tiles: self.state.falling_piece.tiles.clone(),
+            });
+            let locked_piece = &self.state.falling_piece;
+            let fault = self.finesse.as_mut().and_then(|trainer| trainer.check_placement(locked_piece));
+            if let Some(fault) = fault {
+                self.emit(GameEvent::FinesseFault(fault));
+            }
+
+            if let Some(top_out) = self.check_lock_out() {
+                if self.settings.mode.is_practice() {
+                    self.clear_stack();
+                    self.begin_spawn_delay();
+                } else {
+                    self.end_game(top_out);
+                }
+                return;
+            }
+
+            let tspin = self.detect_tspin();
+            let lines = self.find_complete_lines();
+            if !lines.is_empty() || tspin != TSpin::None {
+                self.emit(GameEvent::LinesCleared { count: lines.len(), tspin });
+            }
+
+            self.score_lock(lines.len() as u32, tspin);
+            self.count_lock(&lines, tspin);
+            if self.settings.mode.is_goal_reached(&self.state) {
+                self.clear_lines(&lines);
+                self.reach_goal();
+                return;
+            }
+
+            if self.settings.mode.piece_limit().is_some_and(|limit| self.state.pieces_locked >= limit) {
+                self.clear_lines(&lines);
+                self.end_game(TopOut::OutOfPieces);
+                return;
+            }
+
+            if lines.is_empty() {
+                self.begin_spawn_delay();
+            } else if self.line_clear_delay_millis() > 0 {
+                self.state.phase = Phase::ClearingLines {
+                    lines,
+                    elapsed_millis: 0,
+                    duration_millis: self.line_clear_delay_millis(),
+                };
+                self.state.phase_start_millis = self.state.time_millis;
+            } else {
+                self.clear_lines(&lines);
+                self.begin_spawn_delay();
+            }
+
+            return;
+        }
+
+        self.move_piece(Tile::new(0, 1));
+        self.state.last_move_millis = self.state.time_millis;
+    }
+
+    /// Applies the three-corner rule to a T piece that locked right after rotating. A T-spin is
+    /// only a mini if the corners it points to are not both filled, unless the rotation needed
+    /// the last kick test to fit.
+    fn detect_tspin(&self) -> TSpin {
+        let piece = &self.state.falling_piece;
+        let kick = match self.last_rotation_kick {
+            Some(kick) if piece.kind == PieceKind::T => kick,
+            _ => return TSpin::None,
+        };
+
+        let center = piece.origin + Tile::new(1, 1);
+        let corners = [Tile::new(-1, -1), Tile::new(1, -1), Tile::new(1, 1), Tile::new(-1, 1)];
+        let is_filled = |corner: &Tile| {
+            let tile = center + *corner;
+            !self.state.map.contains(tile) || self.state.map[tile].is_set
+        };
+
+        if corners.iter().filter(|corner| is_filled(corner)).count() < 3 {
+            return TSpin::None;
+        }
+
+        let front_corners = [
+            corners[piece.rotation_index % 4],
+            corners[(piece.rotation_index + 1) % 4],
+        ];
+        if front_corners.iter().all(is_filled) || kick == Some(LAST_KICK_TEST_INDEX) {
+            TSpin::Full
+        } else {
+            TSpin::Mini
+        }
+    }
+
+    fn check_lock_out(&self) -> Option<TopOut> {
+        let tiles = &self.state.falling_piece.tiles;
+        if tiles.iter().all(|tile| tile.y < 0) {
+            return Some(TopOut::LockOut);
+        }
+
+        if self.settings.partial_lock_out && tiles.iter().any(|tile| tile.y < 0) {
+            return Some(TopOut::PartialLockOut);
+        }
+
+        None
+    }
+
+    /// Counts the lines a locked piece cleared and scores them at the level they were cleared on.
+    fn score_lock(&mut self, count: u32, tspin: TSpin) {
+        let count = if self.settings.big { count * BIG_SCALE as u32 } else { count };
+        let state = &mut self.state;
+        state.score += line_clear_points(count as usize, tspin) * state.level as u64;
+        state.lines_cleared += count;
+        while (state.splits_millis.len() as u32 + 1) * SPLIT_LINES <= state.lines_cleared {
+            state.splits_millis.push(state.time_millis);
+        }
+
+        let level = match self.settings.mode.max_level() {
+            Some(max_level) => level_for_lines(state.lines_cleared).min(max_level),
+            None => level_for_lines(state.lines_cleared),
+        };
+        if level > state.level {
+            state.level = level;
+            self.emit(GameEvent::LevelUp { level });
+        }
+    }
+
+    /// Updates the counters of locked pieces and of the kinds of line clears goals ask for.
+    fn count_lock(&mut self, lines: &[i16], tspin: TSpin) {
+        let map = &self.state.map;
+        let garbage_lines = lines.iter().filter(|y| map.is_garbage_row(**y)).count() as u32;
+        let set_tiles = map.tiles.iter().flatten().filter(|map_tile| map_tile.is_set).count();
+        let is_perfect_clear = !lines.is_empty() && set_tiles == lines.len() * map.width as usize;
+
+        let state = &mut self.state;
+        state.pieces_locked += 1;
+        state.garbage_lines_cleared += garbage_lines;
+        state.perfect_clears += is_perfect_clear as u32;
+        state.tspin_doubles += (tspin == TSpin::Full && lines.len() == 2) as u32;
+    }
+
+    fn reach_goal(&mut self) {
+        self.goal_reached = true;
+        self.emit(GameEvent::GoalReached);
+    }
+
+    fn begin_spawn_delay(&mut self) {
+        if self.spawn_delay_millis() > 0 {
+            self.state.phase = Phase::Spawning;
+            self.state.phase_start_millis = self.state.time_millis;
+        } else {
+            self.spawn_piece();
+        }
+    }
+
+    pub(crate) fn spawn_piece(&mut self) {
+        self.state.phase = Phase::Falling;
+        self.state.falling_piece = match self.state.next_piece_kind.take() {
+            Some(kind) => spawn_piece_of_type(kind.piece_type(), self.settings.width, self.settings.buffer_height),
+            None => Self::create_piece(&mut self.piece_type_selector, &self.settings),
+        };
+        self.last_rotation_kick = None;
+        self.emit(GameEvent::PieceSpawned { kind: self.state.falling_piece.kind });
+        if !self.check_block_out() {
+            return;
+        }
+
+        self.state.last_move_millis = self.state.time_millis;
+    }
+
+    /// Ends the game if the piece that just spawned overlaps the stack, or clears the stack
+    /// instead in practice mode. Returns whether the game goes on.
+    fn check_block_out(&mut self) -> bool {
+        if are_valid_positions(&self.state.map, &self.state.falling_piece.tiles) {
+            return true;
+        }
+
+        if !self.settings.mode.is_practice() {
+            self.end_game(TopOut::BlockOut);
+            return false;
+        }
+
+        self.clear_stack();
+        true
+    }
+
+    /// Empties the map, which is how practice mode gets out of topping out.
+    fn clear_stack(&mut self) {
+        self.state.map = Map::new(self.settings.width, self.settings.height, self.settings.buffer_height);
+        self.emit(GameEvent::StackCleared);
+    }
+
+    fn push_undo_state(&mut self) {
+        let mut state = self.state.clone();
+        state.falling_piece = spawn_piece_of_type(state.falling_piece.kind.piece_type(), self.settings.width, self.settings.buffer_height);
+        self.undo_history.push(state);
+        self.redo_history.clear();
+    }
+
+    /// Goes back to the state before the last piece locked, with that piece at its spawn
+    /// position again. Only works in practice mode, and the game time keeps going forward. The
+    /// piece type selector isn't rewound, so the pieces after the undone one come from where the
+    /// sequence is now rather than repeating the ones that came before.
+    pub fn undo(&mut self) {
+        if let Some(state) = self.undo_history.pop() {
+            let current_state = self.restore_state(state);
+            self.redo_history.push(current_state);
+        }
+    }
+
+    /// Goes forward to the state the last undo left.
+    pub fn redo(&mut self) {
+        if let Some(state) = self.redo_history.pop() {
+            let current_state = self.restore_state(state);
+            self.undo_history.push(current_state);
+        }
+    }
+
+    fn restore_state(&mut self, mut state: GameState) -> GameState {
+        state.time_millis = self.state.time_millis;
+        state.last_move_millis = self.state.time_millis;
+        state.phase_start_millis = self.state.time_millis;
+        self.last_rotation_kick = None;
+        if let Some(trainer) = &mut self.finesse {
+            trainer.presses = 0;
+        }
+        mem::replace(&mut self.state, state)
+    }
+
+    fn find_complete_lines(&self) -> Vec<i16> {
+        let map = &self.state.map;
+        (-(map.buffer_height as i16)..map.height as i16)
+            .filter(|&y| (0..map.width as i16).all(|x| map[Tile::new(x, y)].is_set))
+            .collect()
+    }
+
+    fn clear_lines(&mut self, lines: &[i16]) {
+        for &y in lines {
+            let line_index = self.state.map.row_index(y);
+            self.clear_line(line_index);
+        }
+    }
+
+    fn clear_line(&mut self, line_index: usize) {
+        for column in &mut self.state.map.tiles {
+            for i in (1..=line_index).rev() {
+                column[i].is_set = column[i - 1].is_set;
+                column[i].is_garbage = column[i - 1].is_garbage;
+                column[i].kind = column[i - 1].kind;
+                column[i].set_millis = column[i - 1].set_millis;
+            }
+
+            column[0].is_set = false;
+            column[0].is_garbage = false;
+            column[0].kind = None;
+            column[0].set_millis = 0;
+        }
+    }
+
+    /// Pushes the stack up and fills the bottom with garbage `rows`, the last one being the lowest.
+    /// A falling piece that ends up overlapping the stack is pushed up with it, as far as needed.
+    pub(crate) fn insert_garbage_rows(&mut self, rows: &[Vec<bool>]) {
+        let count = rows.len() as i16;
+        let pushed_out = self.state.map.insert_rows_at_bottom(rows, self.state.time_millis);
+        if let Phase::ClearingLines { lines, .. } = &mut self.state.phase {
+            lines.iter_mut().for_each(|y| *y -= count);
+        }
+        self.emit(GameEvent::GarbageAdded { rows: rows.len() });
+
+        if pushed_out {
+            self.end_game(TopOut::GarbageOut);
+            return;
+        }
+
+        if self.state.phase != Phase::Falling || are_valid_positions(&self.state.map, &self.state.falling_piece.tiles) {
+            return;
+        }
+
+        let top_y = self.state.falling_piece.tiles.iter().map(|tile| tile.y).min().unwrap_or(0);
+        let max_push = top_y + self.state.map.buffer_height as i16;
+        let push = (1..=max_push).find(|push| {
+            let mut tiles = self.state.falling_piece.tiles.clone();
+            move_tiles(&mut tiles, Tile::new(0, -push));
+            are_valid_positions(&self.state.map, &tiles)
+        });
+
+        match push {
+            Some(push) => self.move_piece(Tile::new(0, -push)),
+            None => self.end_game(TopOut::GarbageOut),
+        }
+    }
+
+    pub(crate) fn try_rotate_clockwise(&mut self) {
+        self.try_rotate(true);
+    }
+
+    pub(crate) fn try_rotate_counterclockwise(&mut self) {
+        self.try_rotate(false);
+    }
+
+    fn try_rotate(&mut self, clockwise: bool) {
+        if let Some((rotated_piece, kick)) = rotate_with_kicks(&mut self.state, clockwise) {
+            self.state.falling_piece = rotated_piece;
+            self.last_rotation_kick = Some(kick);
+            self.emit(GameEvent::Rotated { clockwise, kick });
+        }
+    }
+
+    fn move_piece(&mut self, delta: Tile) {
+        move_tiles(&mut self.state.falling_piece.tiles, delta);
+        self.state.falling_piece.origin += delta;
+        self.last_rotation_kick = None;
+        self.emit(GameEvent::PieceMoved { delta });
+    }
+
+    fn can_move_down(&self) -> bool {
+        can_move_down(&self.state)
+    }
+
+    pub fn create_piece(piece_type_selector: &mut PTS, settings: &GameSettings) -> Piece {
+        let piece_type = piece_type_selector.select_piece_type(&ALL_PIECES);
+        spawn_piece_of_type(piece_type, settings.width, settings.buffer_height)
+    }
+}
+
+pub(crate) fn can_move_down(state: &GameState) -> bool {
+    can_move_by(state, Tile::new(0, 1))
+}
+
+pub(crate) fn can_move_left(state: &mut GameState) -> bool {
+    can_move_by(state, Tile::new(-1, 0))
+}
+
+pub(crate) fn can_move_right(state: &mut GameState) -> bool {
+    can_move_by(state, Tile::new(1, 0))
+}
+
+fn can_move_by(state: &GameState, delta: Tile) -> bool {
+    for tile in &state.falling_piece.tiles {
+        let next_tile = *tile + delta;
+        if !state.map.contains(next_tile) || state.map[next_tile].is_set {
+            return false;
+        }
+    }
+
+    true
+}
+
+pub(crate) fn rotate_clockwise(piece: &mut Piece) {
+    for tile in &mut piece.tiles {
+        let delta_from_origin = *tile - piece.origin;
+        let new_delta_from_origin = Tile::new(piece.bounding_box_size - 1 - delta_from_origin.y, delta_from_origin.x);
+        *tile = piece.origin + new_delta_from_origin;
+    }
+
+    piece.rotation_index = (piece.rotation_index + 1) % 4;
+}
+
+pub(crate) fn rotate_counterclockwise(piece: &mut Piece) {
+    for tile in &mut piece.tiles {
+        let delta_from_origin = *tile - piece.origin;
+        let new_delta_from_origin = Tile::new(delta_from_origin.y, piece.bounding_box_size - 1 - delta_from_origin.x);
+        *tile = piece.origin + new_delta_from_origin;
+    }
+
+    piece.rotation_index = (piece.rotation_index + 3) % 4;
+}
+
+/// The falling piece of `state` rotated, and kicked when it doesn't fit in place, with the index
+/// of the kick test that made it fit. Returns `None` when no kick test fits.
+pub(crate) fn rotate_with_kicks(state: &mut GameState, clockwise: bool) -> Option<(Piece, Option<usize>)> {
+    let mut rotated_piece = state.falling_piece.clone();
+    if clockwise {
+        rotate_clockwise(&mut rotated_piece);
+    } else {
+        rotate_counterclockwise(&mut rotated_piece);
+    }
+
+    if are_valid_positions(&state.map, &rotated_piece.tiles) {
+        return Some((rotated_piece, None));
+    }
+
+    let array_offset = if clockwise { 0 } else { 1 };
+    let tests_index = rotated_piece.rotation_index * 2 + array_offset;
+    let kick = match rotated_piece.bounding_box_size {
+        3 => kick_piece_with(state, &mut rotated_piece, SIZE_3_KICK_TESTS[tests_index]),
+        4 => kick_piece_with(state, &mut rotated_piece, SIZE_4_KICK_TESTS[tests_index]),
+        _ => None
+    }?;
+
+    Some((rotated_piece, Some(kick)))
+}
+
+pub(crate) fn are_valid_positions(map: &Map, tiles: &Vec<Tile>) -> bool {
+    for tile in tiles {
+        if !map.contains(*tile) {
+            return false;
+        }
+
+        if map[*tile].is_set {
+            return false;
+        }
+    }
+
+    true
+}
+
+fn kick_piece_with(state: &mut GameState, piece: &mut Piece, test_delta_tiles: [Tile; 4]) -> Option<usize> {
+    for (index, test_delta_tile) in test_delta_tiles.iter().enumerate() {
+        let mut test_tiles = piece.tiles.clone();
+        move_tiles(&mut test_tiles, *test_delta_tile);
+
+        if are_valid_positions(&state.map, &test_tiles) {
+            piece.tiles = test_tiles;
+            piece.origin += *test_delta_tile;
+            return Some(index);
+        }
+    }
+
+    None
+}
+
+fn move_tiles(tiles: &mut Vec<Tile>, delta: Tile) {
+    for tile in tiles {
+        *tile += delta;
+    }
+}
+
+/// The error for a file or setting that can't be read into a game.
+pub(crate) fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
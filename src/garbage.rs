@@ -0,0 +1,16 @@
+use crate::pieces::split_mix64;
+
+/// Rows at the top of the visible playfield that starting garbage never fills, so pieces can
+/// still spawn and move.
+pub const GARBAGE_FREE_ROWS: u16 = 4;
+
+/// A row of garbage with a single hole in a random column. `true` marks a filled tile. A map
+/// with no columns gets an empty row.
+pub fn garbage_row(width: u16, rng_state: &mut u64) -> Vec<bool> {
+    if width == 0 {
+        return Vec::new();
+    }
+
+    let hole = (split_mix64(rng_state) % width as u64) as u16;
+    (0..width).map(|x| x != hole).collect()
+}
@@ -0,0 +1,40 @@
+use crate::game::GameState;
+
+/// Grades and the score needed for each, from the lowest to the highest below GM.
+pub const GRADES: [(&str, u64); 18] = [
+    ("9", 0),
+    ("8", 400),
+    ("7", 800),
+    ("6", 1_400),
+    ("5", 2_000),
+    ("4", 3_500),
+    ("3", 5_500),
+    ("2", 8_000),
+    ("1", 12_000),
+    ("S1", 16_000),
+    ("S2", 22_000),
+    ("S3", 30_000),
+    ("S4", 40_000),
+    ("S5", 52_000),
+    ("S6", 66_000),
+    ("S7", 82_000),
+    ("S8", 100_000),
+    ("S9", 120_000),
+];
+pub const GRAND_MASTER: &str = "GM";
+/// A completed master game earns GM with an S9 score within this time.
+pub const GRAND_MASTER_TIME_MILLIS: u128 = 8 * 60 * 1000;
+
+/// The grade earned so far in master mode. GM is only awarded once the game is completed.
+pub fn master_grade(state: &GameState, completed: bool) -> &'static str {
+    let (grade, _) = GRADES.iter()
+        .rev()
+        .find(|(_, score)| state.score >= *score)
+        .unwrap_or(&GRADES[0]);
+
+    if completed && *grade == GRADES[GRADES.len() - 1].0 && state.time_millis <= GRAND_MASTER_TIME_MILLIS {
+        return GRAND_MASTER;
+    }
+
+    grade
+}
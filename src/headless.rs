@@ -0,0 +1,68 @@
+use std::io;
+use std::mem;
+use crate::game::*;
+use crate::pieces::SeededPieceTypeSelector;
+use crate::input::*;
+use crate::time::ManualClock;
+use crate::drawing::NoopDrawing;
+use crate::events::GameEvent;
+use crate::builder::GameBuilder;
+
+type HeadlessGame = Game<QueuedInput, SeededPieceTypeSelector, ManualClock, NoopDrawing>;
+
+/// Runs a game without a terminal, driven by explicit actions and time steps. The same seed,
+/// settings and sequence of steps always produce the same game, and a simulation can be cloned to
+/// explore different continuations from the same position.
+#[derive(Clone)]
+pub struct Simulation {
+    game: HeadlessGame
+}
+
+impl Simulation {
+    /// Creates a simulation at time zero with its first piece already spawned, or fails if
+    /// `settings` aren't valid.
+    pub fn new(settings: GameSettings, seed: u64) -> io::Result<Simulation> {
+        Ok(Simulation {
+            game: GameBuilder::new()
+                .piece_type_selector(SeededPieceTypeSelector::new(seed))
+                .clock(ManualClock { now_milliseconds: 0 })
+                .settings(settings)
+                .build()?
+        })
+    }
+
+    /// Advances the clock by `dt_millis`, applies `actions` in order and runs a single frame,
+    /// returning the events it emitted. Steps after the game ended emit nothing.
+    pub fn step(&mut self, actions: &[InputResult], dt_millis: u128) -> Vec<GameEvent> {
+        if self.is_over() {
+            return Vec::new();
+        }
+
+        self.game.clock.now_milliseconds += dt_millis;
+        self.game.input.inputs.extend(actions.iter().copied());
+        self.game.run_frame();
+        self.game.input.inputs.clear();
+
+        mem::take(&mut self.game.events)
+    }
+
+    pub fn state(&self) -> &GameState {
+        &self.game.state
+    }
+
+    pub fn settings(&self) -> &GameSettings {
+        &self.game.settings
+    }
+
+    pub fn top_out(&self) -> Option<TopOut> {
+        self.game.top_out
+    }
+
+    pub fn is_over(&self) -> bool {
+        self.game.is_over()
+    }
+
+    pub fn time_millis(&self) -> u128 {
+        self.game.clock.now_milliseconds
+    }
+}
@@ -1,7 +1,7 @@
-use crossterm::event::{poll, read, Event, KeyCode, KeyEvent};
-use std::time::Duration;
+use std::collections::VecDeque;
+use crate::pieces::PieceKind;
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
 pub enum InputResult {
     MoveLeft,
     MoveRight,
@@ -9,66 +9,27 @@ pub enum InputResult {
     RotateClockwise,
     RotateCounterClockwise,
     ExitGame,
+    /// Puts the last placed piece back, in practice mode.
+    Undo,
+    /// Places again the last piece that was undone, in practice mode.
+    Redo,
+    /// Makes the next piece that spawns be of the given kind, in practice mode.
+    ChooseNextPiece(PieceKind),
 }
 
 pub trait InputSource {
     fn read_input(&mut self) -> Option<InputResult>;
 }
 
-pub struct CrosstermInput {}
-
-impl InputSource for CrosstermInput {
-    fn read_input(&mut self) -> Option<InputResult> {
-        match poll(Duration::from_secs(0)) {
-            Ok(has_input) => {
-                match has_input {
-                    true => convert_input(),
-                    false => None
-                }
-            }
-            Err(error) => {
-                println!("Error reading input poll: {}", error);
-                None
-            }
-        }
-    }
+/// Returns queued inputs in order, one per call.
+#[derive(Clone, Default)]
+pub struct QueuedInput {
+    pub inputs: VecDeque<InputResult>
 }
 
-fn convert_input() -> Option<InputResult> {
-    match read() {
-        Ok(read_key) => {
-            match read_key {
-                Event::Key(KeyEvent {
-                               code: KeyCode::Left,
-                               ..
-                           }) => Some(InputResult::MoveLeft),
-                Event::Key(KeyEvent {
-                               code: KeyCode::Right,
-                               ..
-                           }) => Some(InputResult::MoveRight),
-                Event::Key(KeyEvent {
-                               code: KeyCode::Up,
-                               ..
-                           }) => Some(InputResult::RotateClockwise),
-                Event::Key(KeyEvent {
-                               code: KeyCode::Char('z'),
-                               ..
-                           }) => Some(InputResult::RotateCounterClockwise),
-                Event::Key(KeyEvent {
-                               code: KeyCode::Down,
-                               ..
-                           }) => Some(InputResult::MoveDown),
-                Event::Key(KeyEvent {
-                               code: KeyCode::Esc,
-                               ..
-                           }) => Some(InputResult::ExitGame),
-                _ => None,
-            }
-        }
-        Err(error) => {
-            println!("Error reading input: {}", error);
-            None
-        }
+impl InputSource for QueuedInput {
+    fn read_input(&mut self) -> Option<InputResult> {
+        self.inputs.pop_front()
     }
 }
 
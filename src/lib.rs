@@ -0,0 +1,50 @@
+//! A tetris engine. The terminal frontend lives in the `terminal` module, behind the `terminal`
+//! feature, so bots and tools can depend on the engine alone.
+
+pub mod tiles;
+pub mod pieces;
+pub mod game;
+pub mod drawing;
+pub mod input;
+pub mod time;
+pub mod events;
+pub mod modes;
+pub mod scoring;
+pub mod garbage;
+pub mod grading;
+pub mod finesse;
+pub mod puzzle;
+pub mod board;
+pub mod fumen;
+pub mod placements;
+pub mod builder;
+pub mod save;
+pub mod replay;
+pub mod playback;
+pub mod headless;
+#[cfg(feature = "terminal")]
+pub mod terminal;
+#[cfg(test)]
+mod tests;
+
+pub use tiles::*;
+pub use pieces::*;
+pub use game::*;
+pub use drawing::*;
+pub use input::*;
+pub use time::*;
+pub use events::*;
+pub use modes::*;
+pub use scoring::*;
+pub use garbage::*;
+pub use grading::*;
+pub use finesse::{FinesseTable, FinesseTrainer, FinesseFault};
+pub use puzzle::{Puzzle, load_puzzle_pack, shipped_puzzle_pack};
+pub use board::Board;
+pub use fumen::{FumenPage, decode_fumen, encode_fumen, replay_to_fumen};
+pub use placements::{Placement, reachable_placements};
+pub use builder::GameBuilder;
+pub use save::SavedGame;
+pub use replay::{Replay, RecordedInput, board_checksum};
+pub use playback::{Playback, FRAME_MILLIS, PLAYBACK_SPEEDS};
+pub use headless::Simulation;
@@ -1,349 +1,338 @@
-mod tiles;
-mod pieces;
-mod game;
-mod drawing;
-mod input;
-mod time;
-#[cfg(test)]
-mod tests;
-
-use std::io::stdout;
-use tiles::*;
-use pieces::*;
-use game::*;
-use drawing::*;
-use input::*;
-use crate::time::{ClockInstant, Clock, StdClock};
+use std::env;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tetris::*;
+use tetris::terminal::*;
 
-fn main() {
-    loop {
-        let mut game = Game::new(
-            CrosstermInput {},
-            RandomPieceTypeSelector {},
-            StdClock {},
-        StdoutDrawing { stdout: stdout() });
-        
-        game.drawing.init();
-        
-        match game.play_until_finished() {
-            FrameResult::GameQuitRequested => break,
-            _ => continue
-        }
-    }
-}
+const SAVE_FILE: &str = "save";
+const PUZZLE_DIRECTORY: &str = "puzzles";
 
-impl<I: InputSource, PTS: PieceTypeSelector, TCI: ClockInstant, TC: Clock<TCI>, TD: Drawing> Game<I, PTS, TCI, TC, TD> {
-    pub fn new(input: I, piece_type_selector: PTS, clock: TC, drawing: TD) -> Game<I, PTS, TCI, TC, TD> {
-        let now = clock.now();
-        Game {
-            state: GameState {
-                map: Game::<I, PTS, TCI, TC, TD>::initialize_map(),
-                falling_piece: Game::<I, PTS, TCI, TC, TD>::create_piece(&piece_type_selector),
-            },
-            clock,
-            last_move_instant: now,
-            ended: false,
-            input,
-            piece_type_selector,
-            drawing
-        }
-    }
-    
-    fn play_until_finished(&mut self) -> FrameResult {
-        loop {
-            match self.run_frame() {
-                FrameResult::GameInProgress => continue,
-                a => return a
-            }
-        }
-    }
+type TerminalGame<PTS = SeededPieceTypeSelector> = Game<CrosstermInput, PTS, StdClock, StdoutDrawing>;
 
-    fn run_frame(&mut self) -> FrameResult {
-        if self.read_input() {
-            return FrameResult::GameQuitRequested;
-        }
-
-        if self.ended {
-            return FrameResult::PlayerLost;
-        }
-
-        self.apply_gravity();
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if let Some(position) = args.iter().position(|arg| arg == "--replay") {
+        let replay_path = match args.get(position + 1) {
+            Some(path) => PathBuf::from(path),
+            None => {
+                eprintln!("Usage: tetris --replay <file>");
+                return;
+            }
+        };
 
-        if self.ended {
-            return FrameResult::PlayerLost;
+        match Replay::load_from(&replay_path).and_then(Playback::new) {
+            Ok(playback) => play_replay(playback),
+            Err(error) => eprintln!("Could not load replay from {}: {}", replay_path.display(), error),
         }
-
-        self.drawing.draw(&self.state);
-        FrameResult::GameInProgress
+        return;
     }
 
-    fn initialize_map() -> Map {
-        let mut tiles: [[MapTile; HEIGHT as usize]; WIDTH as usize] = [[MapTile {
-            tile: Tile::new(0, 0),
-            is_set: false,
-        }; HEIGHT as usize]; WIDTH as usize];
-
-        for x in 0usize..WIDTH as usize {
-            for y in 0usize..HEIGHT as usize {
-                tiles[x][y].tile = Tile {
-                    x: x as i16,
-                    y: y as i16,
-                }
+    if let Some(position) = args.iter().position(|arg| arg == "--puzzle") {
+        let puzzle_path = match args.get(position + 1) {
+            Some(path) => PathBuf::from(path),
+            None => {
+                eprintln!("Usage: tetris --puzzle <file>");
+                return;
             }
-        }
+        };
 
-        Map { tiles }
-    }
-
-    fn read_input(&mut self) -> bool {
-        loop {
-            let input_read = self.input.read_input();
-            match input_read.as_ref() {
-                Some(input) => match input {
-                    InputResult::MoveLeft => self.move_left(),
-                    InputResult::MoveRight => self.move_right(),
-                    InputResult::MoveDown => self.fall_piece(),
-                    InputResult::RotateClockwise => self.try_rotate_clockwise(),
-                    InputResult::RotateCounterClockwise => self.try_rotate_counterclockwise(),
-                    InputResult::ExitGame => return true,
-                },
-                None => return false
+        match Puzzle::load_from(&puzzle_path) {
+            Ok(puzzle) => {
+                play_puzzle(&puzzle);
             }
+            Err(error) => eprintln!("Could not load puzzle from {}: {}", puzzle_path.display(), error),
         }
+        return;
     }
 
-    fn move_left(&mut self) {
-        if can_move_left(&mut self.state) {
-            self.move_piece(Tile::new(-1, 0));
-        }
-    }
-
-    fn move_right(&mut self) {
-        if can_move_right(&mut self.state) {
-            self.move_piece(Tile::new(1, 0));
-        }
-    }
+    if let Some(position) = args.iter().position(|arg| arg == "--puzzles") {
+        let directory = match args.get(position + 1).filter(|arg| !arg.starts_with("--")) {
+            Some(directory) => PathBuf::from(directory),
+            None if !data_file(PUZZLE_DIRECTORY).is_dir() => {
+                browse_puzzles(&shipped_puzzle_pack());
+                return;
+            }
+            None => data_file(PUZZLE_DIRECTORY),
+        };
 
-    fn apply_gravity(&mut self) {
-        if self.last_move_instant.difference_millis(&self.clock.now()) > 1000 {
-            self.fall_piece();
+        match load_puzzle_pack(&directory) {
+            Ok(pack) => browse_puzzles(&pack),
+            Err(error) => eprintln!("Could not load puzzles from {}: {}", directory.display(), error),
         }
+        return;
     }
 
-    fn fall_piece(&mut self) {
-        if !self.can_move_down() {
-            for tile in &mut self.state.falling_piece.tiles {
-                self.state.map.tiles[tile.x as usize][tile.y as usize].is_set = true;
-            }
+    let mode = parse_mode(&args);
+    let stack_visibility = parse_stack_visibility(&args);
+    let big = args.iter().any(|arg| arg == "--big");
 
-            self.clear_complete_lines();
+    let save_path = data_file(SAVE_FILE);
+    let mut saved_game = None;
 
-            self.state.falling_piece = Game::<I, PTS, TCI, TC, TD>::create_piece(&self.piece_type_selector);
-            if !are_valid_positions(&self.state.map, &self.state.falling_piece.tiles) {
-                self.ended = true;
+    if args.iter().any(|arg| arg == "--resume") {
+        match SavedGame::load_from(&save_path) {
+            Ok(game) => saved_game = Some(game),
+            Err(error) => {
+                eprintln!("Could not resume game from {}: {}", save_path.display(), error);
                 return;
             }
-
-            self.last_move_instant = self.clock.now();
-            return;
         }
-
-        self.move_piece(Tile::new(0, 1));
-        self.last_move_instant = self.clock.now();
     }
 
-    fn clear_complete_lines(&mut self) {
-        for i in 0..HEIGHT as usize {
-            let mut all_set = true;
-            for x in 0..WIDTH as usize {
-                if !self.state.map.tiles[x][i].is_set {
-                    all_set = false;
-                    break;
-                }
+    loop {
+        let settings = match &saved_game {
+            Some(saved_game) => saved_game.settings,
+            None => new_game_settings(mode, big),
+        };
+        let game = GameBuilder::new()
+            .input(CrosstermInput {})
+            .piece_type_selector(SeededPieceTypeSelector::new(new_seed()))
+            .drawing(StdoutDrawing { stack_visibility, ..StdoutDrawing::for_settings(&settings) })
+            .settings(settings)
+            .build();
+        let mut game = match game {
+            Ok(game) => game,
+            Err(error) => {
+                eprintln!("Could not start game: {}", error);
+                return;
             }
+        };
 
-            if all_set {
-                self.clear_line(i);
+        match saved_game.take() {
+            Some(saved_game) => {
+                if let Err(error) = game.resume(saved_game) {
+                    eprintln!("Could not resume game from {}: {}", save_path.display(), error);
+                    return;
+                }
             }
+            None => game.start_recording(),
         }
-    }
-
-    fn clear_line(&mut self, line_index: usize) {
-        for i in (1..=line_index).rev() {
-            for x in 0..WIDTH as usize {
-                self.state.map.tiles[x][i].is_set = self.state.map.tiles[x][i - 1].is_set;
+        
+        game.drawing.init();
+        
+        let personal_best = PersonalBest::load(&game.settings);
+        let result = play(&mut game, personal_best.as_ref());
+        if let Some(replay) = game.finish_recording() {
+            let replay_path = data_file(&format!("{}.replay", unix_seconds()));
+            if let Err(error) = replay.save_to(&replay_path) {
+                eprintln!("Could not save replay to {}: {}", replay_path.display(), error);
             }
         }
 
-        for x in 0..WIDTH as usize {
-            self.state.map.tiles[x][0].is_set = false;
-        }
-    }
-
-    fn try_rotate_clockwise(&mut self) {
-        let mut rotated_piece = self.state.falling_piece.clone();
-        rotate_clockwise(&mut rotated_piece);
+        match result {
+            FrameResult::GameQuitRequested => {
+                if let Err(error) = game.save().save_to(&save_path) {
+                    eprintln!("Could not save game to {}: {}", save_path.display(), error);
+                }
+                break;
+            }
+            result => {
+                let _ = fs::remove_file(&save_path);
+                let hides_stack = stack_visibility != StackVisibility::Visible;
+                if hides_stack {
+                    game.drawing.stack_visibility = StackVisibility::Visible;
+                    game.drawing.draw(&game.state);
+                }
 
-        if !are_valid_positions(&self.state.map, &rotated_piece.tiles) {
-            if !self.kick_piece(&mut rotated_piece, 0) {
-                return;
+                if result == FrameResult::GoalReached || game.settings.mode.ends_by_topping_out() {
+                    show_result(&mut game, personal_best);
+                } else if hides_stack {
+                    game.drawing.draw_banner(&game.state.map, "GAME OVER");
+                    wait_for_key();
+                }
             }
         }
-
-        self.state.falling_piece = rotated_piece;
     }
+}
 
-    fn try_rotate_counterclockwise(&mut self) {
-        let mut rotated_piece = self.state.falling_piece.clone();
-        rotate_counterclockwise(&mut rotated_piece);
-
-        if !are_valid_positions(&self.state.map, &rotated_piece.tiles) {
-            if !self.kick_piece(&mut rotated_piece, 1) {
-                return;
-            }
+/// Shows the result of a finished game over the board, saving it if it is a new personal best,
+/// and waits for a key.
+fn show_result(game: &mut TerminalGame, mut personal_best: Option<PersonalBest>) {
+    let is_new_best = personal_best.as_ref()
+        .is_none_or(|best| best.is_beaten_by(game.settings.mode, &game.state));
+    if is_new_best {
+        let best = PersonalBest::from_state(&game.state);
+        if let Err(error) = best.save(&game.settings) {
+            eprintln!("Could not save personal best: {}", error);
         }
-
-        self.state.falling_piece = rotated_piece;
+        personal_best = Some(best);
     }
 
-    fn kick_piece(&mut self, piece: &mut Piece, array_offset: usize) -> bool {
-        let tests_index = piece.rotation_index * 2 + array_offset;
+    let mut hud = game_hud(&game.state, &game.settings, personal_best.as_ref());
+    hud.extend(vec![
+        String::new(),
+        result_message(&game.state, &game.settings, is_new_best),
+        "Press any key".to_string(),
+    ]);
+    game.drawing.draw_hud(&game.state.map, &hud);
+    game.drawing.draw_banner(&game.state.map, result_banner(game.settings.mode));
+    wait_for_key();
+}
 
-        match piece.bounding_box_size {
-            3 => kick_piece_with(&mut self.state, piece, SIZE_3_KICK_TESTS[tests_index]),
-            4 => kick_piece_with(&mut self.state, piece, SIZE_4_KICK_TESTS[tests_index]),
-            _ => false
-        }
+/// Picks the game mode from `--sprint [lines]`, `--ultra [seconds]`, `--dig [rows]`,
+/// `--survival [seconds]`, `--master`, `--zen`, `--finesse` or `--marathon [lines | endless]`.
+fn parse_mode(args: &[String]) -> GameMode {
+    let option = |name: &str| args.iter()
+        .position(|arg| arg == name)
+        .map(|position| args.get(position + 1).map(String::as_str));
+
+    if let Some(lines) = option("--sprint") {
+        return GameMode::Sprint {
+            lines: lines.and_then(|lines| lines.parse().ok()).unwrap_or(DEFAULT_SPRINT_LINES),
+        };
     }
 
-    fn move_piece(&mut self, delta: Tile) {
-        move_tiles(&mut self.state.falling_piece.tiles, delta);
-        self.state.falling_piece.origin = self.state.falling_piece.origin + delta;
+    if let Some(seconds) = option("--ultra") {
+        return GameMode::Ultra {
+            duration_millis: seconds.and_then(parse_seconds_as_millis).unwrap_or(DEFAULT_ULTRA_MILLIS),
+        };
     }
 
-    fn can_move_down(&self) -> bool {
-        for tile in &self.state.falling_piece.tiles {
-            if tile.y == HEIGHT as i16 - 1 {
-                return false;
-            }
+    if let Some(rows) = option("--dig") {
+        return GameMode::Dig {
+            rows: rows.and_then(|rows| rows.parse().ok()).unwrap_or(DEFAULT_DIG_ROWS),
+        };
+    }
 
-            if self.state.map[*tile + Tile::new(0, 1)].is_set {
-                return false;
-            }
-        }
+    if let Some(seconds) = option("--survival") {
+        return GameMode::Survival {
+            interval_millis: seconds.and_then(parse_seconds_as_millis).unwrap_or(DEFAULT_SURVIVAL_INTERVAL_MILLIS),
+        };
+    }
 
-        true
+    if option("--finesse").is_some() {
+        return GameMode::Finesse;
     }
 
-    pub fn create_piece(piece_type_selector: &PTS) -> Piece {
-        let piece_type = piece_type_selector.select_piece_type(&ALL_PIECES);
-        let mut tiles = piece_type.tiles.to_vec();
-        let start_x = WIDTH as i16 / 2 - (piece_type.bounding_box_size as f32 / 2f32).ceil() as i16;
+    if option("--zen").is_some() {
+        return GameMode::Zen;
+    }
 
-        for tile in &mut tiles {
-            tile.x += start_x;
-        }
+    if option("--master").is_some() {
+        return GameMode::Master;
+    }
 
-        Piece {
-            tiles,
-            origin: piece_type.origin + Tile::new(start_x, 0),
-            bounding_box_size: piece_type.bounding_box_size,
-            rotation_index: 0,
-        }
+    match option("--marathon") {
+        Some(Some("endless")) | None => GameMode::Endless,
+        Some(lines) => GameMode::Marathon {
+            lines: lines.and_then(|lines| lines.parse().ok()).unwrap_or(DEFAULT_MARATHON_LINES),
+        },
     }
 }
 
-fn can_move_left(state: &mut GameState) -> bool {
-    for tile in &state.falling_piece.tiles {
-        if tile.x == 0 {
-            return false;
-        }
-
-        if state.map[*tile + Tile::new(-1, 0)].is_set {
-            return false;
+/// Lets the player pick puzzles from a pack and play them until the browser is left.
+fn browse_puzzles(pack: &[(PathBuf, io::Result<Puzzle>)]) {
+    let mut solved = vec![false; pack.len()];
+    let mut selected = 0;
+    while let Some(index) = choose_puzzle(pack, &solved, selected) {
+        selected = index;
+        if let (_, Ok(puzzle)) = &pack[index] {
+            solved[index] |= play_puzzle(puzzle);
         }
     }
-
-    true
 }
 
-fn can_move_right(state: &mut GameState) -> bool {
-    for tile in &state.falling_piece.tiles {
-        if tile.x + 1 >= WIDTH as i16 {
-            return false;
-        }
-
-        if state.map[*tile + Tile::new(1, 0)].is_set {
+/// Plays a puzzle and shows whether it was solved, returning whether it was. Quitting gives up
+/// the puzzle without a result.
+fn play_puzzle(puzzle: &Puzzle) -> bool {
+    let settings = puzzle.settings();
+    let game = GameBuilder::new()
+        .input(CrosstermInput {})
+        .piece_type_selector(puzzle.piece_type_selector())
+        .drawing(StdoutDrawing::for_settings(&settings))
+        .settings(settings)
+        .build();
+    let mut game = match game {
+        Ok(game) => game,
+        Err(error) => {
+            eprintln!("Could not start puzzle {}: {}", puzzle.name, error);
             return false;
         }
-    }
-
-    true
+    };
+    game.state.map = puzzle.map();
+    game.drawing.init();
+
+    let (solved, banner) = match play(&mut game, None) {
+        FrameResult::GoalReached => (true, "SOLVED"),
+        FrameResult::PlayerLost(_) => (false, "FAILED"),
+        _ => return false,
+    };
+
+    let mut hud = game_hud(&game.state, &game.settings, None);
+    hud.extend(vec![String::new(), puzzle.name.clone(), "Press any key".to_string()]);
+    game.drawing.draw(&game.state);
+    game.drawing.draw_hud(&game.state.map, &hud);
+    game.drawing.draw_banner(&game.state.map, banner);
+    wait_for_key();
+    solved
 }
 
-fn rotate_clockwise(piece: &mut Piece) {
-    for tile in &mut piece.tiles {
-        let delta_from_origin = *tile - piece.origin;
-        let new_delta_from_origin = Tile::new(piece.bounding_box_size - 1 - delta_from_origin.y, delta_from_origin.x);
-        *tile = piece.origin + new_delta_from_origin;
+/// Settings of a new game. Big games keep the size of the board on the screen with blocks that
+/// are `BIG_SCALE` times bigger.
+fn new_game_settings(mode: GameMode, big: bool) -> GameSettings {
+    let settings = GameSettings { mode, garbage_seed: new_seed(), ..GameSettings::default() };
+    if !big {
+        return settings;
     }
 
-    piece.rotation_index = (piece.rotation_index + 1) % 4;
-}
-
-fn rotate_counterclockwise(piece: &mut Piece) {
-    for tile in &mut piece.tiles {
-        let delta_from_origin = *tile - piece.origin;
-        let new_delta_from_origin = Tile::new(delta_from_origin.y, piece.bounding_box_size - 1 - delta_from_origin.x);
-        *tile = piece.origin + new_delta_from_origin;
+    GameSettings {
+        width: settings.width / BIG_SCALE,
+        height: settings.height / BIG_SCALE,
+        buffer_height: settings.buffer_height / BIG_SCALE,
+        big,
+        ..settings
     }
-
-    piece.rotation_index = (piece.rotation_index + 3) % 4;
 }
 
-fn are_valid_positions(map: &Map, tiles: &Vec<Tile>) -> bool {
-    for tile in tiles {
-        if tile.y < 0 {
-            return false;
-        }
-
-        if tile.y >= HEIGHT as i16 {
-            return false;
-        }
-
-        if tile.x < 0 {
-            return false;
-        }
-
-        if tile.x >= WIDTH as i16 {
-            return false;
-        }
-
-        if map[*tile].is_set {
-            return false;
-        }
+/// Picks how the stack is drawn from `--fading [seconds]` or `--invisible`, which work with any mode.
+fn parse_stack_visibility(args: &[String]) -> StackVisibility {
+    if args.iter().any(|arg| arg == "--invisible") {
+        return StackVisibility::Invisible;
     }
 
-    true
+    match args.iter().position(|arg| arg == "--fading") {
+        Some(position) => StackVisibility::Fading {
+            visible_millis: args.get(position + 1)
+                .and_then(|seconds| parse_seconds_as_millis(seconds))
+                .unwrap_or(DEFAULT_FADE_MILLIS),
+        },
+        None => StackVisibility::Visible,
+    }
 }
 
-fn kick_piece_with(state: &mut GameState, piece: &mut Piece, test_delta_tiles: [Tile; 4]) -> bool {
-    for test_delta_tile in &test_delta_tiles {
-        let mut test_tiles = piece.tiles.clone();
-        move_tiles(&mut test_tiles, *test_delta_tile);
+/// A number of seconds given as an argument, in milliseconds, if it's a number that fits.
+fn parse_seconds_as_millis(seconds: &str) -> Option<u128> {
+    seconds.parse::<u128>().ok()?.checked_mul(1000)
+}
 
-        if are_valid_positions(&state.map, &test_tiles) {
-            piece.tiles = test_tiles;
-            piece.origin += *test_delta_tile;
-            return true;
+/// Runs the game until it ends, drawing the HUD after every frame.
+fn play<PTS: PieceTypeSelector>(game: &mut TerminalGame<PTS>, personal_best: Option<&PersonalBest>) -> FrameResult {
+    loop {
+        match game.run_frame() {
+            FrameResult::GameInProgress => {
+                let mut hud = game_hud(&game.state, &game.settings, personal_best);
+                if let Some(trainer) = &game.finesse {
+                    hud.extend(finesse_hud(trainer));
+                }
+                game.drawing.draw_hud(&game.state.map, &hud);
+            }
+            result => return result,
         }
     }
+}
 
-    false
+fn unix_seconds() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
 }
 
-fn move_tiles(tiles: &mut Vec<Tile>, delta: Tile) {
-    for tile in tiles {
-        *tile = *tile + delta;
-    }
-}
\ No newline at end of file
+fn new_seed() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_nanos() as u64)
+        .unwrap_or_default()
+}
@@ -0,0 +1,240 @@
+use crate::game::GameState;
+use crate::scoring::LINES_PER_LEVEL;
+use crate::garbage::GARBAGE_FREE_ROWS;
+
+pub const DEFAULT_SPRINT_LINES: u32 = 40;
+pub const DEFAULT_ULTRA_MILLIS: u128 = 120_000;
+pub const DEFAULT_MARATHON_LINES: u32 = 150;
+pub const DEFAULT_DIG_ROWS: u32 = 10;
+pub const DEFAULT_SURVIVAL_INTERVAL_MILLIS: u128 = 8000;
+/// Survival garbage never rises faster than this.
+pub const MIN_SURVIVAL_INTERVAL_MILLIS: u128 = 1000;
+
+/// Speeds of one section of master mode. A section lasts a level, and a gravity interval of zero
+/// is 20G: pieces drop to the floor as soon as they spawn or move.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct SectionTiming {
+    pub gravity_interval_millis: u128,
+    pub spawn_delay_millis: u128,
+    /// How long a piece can rest on the stack before locking. Only moving down resets it.
+    pub lock_delay_millis: u128,
+    pub line_clear_delay_millis: u128,
+}
+
+const fn section(gravity_interval_millis: u128, spawn_delay_millis: u128, lock_delay_millis: u128, line_clear_delay_millis: u128) -> SectionTiming {
+    SectionTiming { gravity_interval_millis, spawn_delay_millis, lock_delay_millis, line_clear_delay_millis }
+}
+
+pub const MASTER_SECTIONS: [SectionTiming; 10] = [
+    section(64, 400, 500, 400),
+    section(11, 400, 500, 400),
+    section(0, 400, 500, 400),
+    section(0, 330, 500, 330),
+    section(0, 270, 500, 270),
+    section(0, 270, 450, 200),
+    section(0, 200, 400, 150),
+    section(0, 200, 350, 100),
+    section(0, 170, 300, 100),
+    section(0, 130, 250, 50),
+];
+pub const MASTER_LINES: u32 = MASTER_SECTIONS.len() as u32 * LINES_PER_LEVEL;
+/// Lines between two consecutive splits.
+pub const SPLIT_LINES: u32 = 10;
+
+/// What a puzzle asks for.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PuzzleGoal {
+    Lines(u32),
+    /// Leave the map empty after a line clear.
+    PerfectClear,
+    /// Clear two lines with a T-spin.
+    TSpinDouble,
+}
+
+/// The rule that decides when a game is won. Every mode can still be lost by topping out.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum GameMode {
+    /// Plays until topping out, with the levels and gravity of a marathon that never ends.
+    #[default]
+    Endless,
+    /// Ends as soon as `lines` lines have been cleared, completing the last level. The result is
+    /// the score.
+    Marathon { lines: u32 },
+    /// Ends as soon as `lines` lines have been cleared. The result is the time it took.
+    Sprint { lines: u32 },
+    /// Ends after `duration_millis` of game time. The result is the score, and gravity stays at
+    /// its level 1 speed.
+    Ultra { duration_millis: u128 },
+    /// Starts with `rows` rows of garbage and ends as soon as all of them are cleared. The result
+    /// is the time it took.
+    Dig { rows: u32 },
+    /// Pushes a garbage row up from the bottom every `interval_millis`, and 10% sooner after each
+    /// row, until the player tops out. The result is the time survived.
+    Survival { interval_millis: u128 },
+    /// Runs through the `MASTER_SECTIONS`, reaching 20G and shrinking delays, and ends after
+    /// `MASTER_LINES` lines with a grade for the score and time.
+    Master,
+    /// Practice without a goal, gravity or topping out. Pieces only move down when dropped, the
+    /// stack is cleared instead of topping out, placements can be undone and redone, and the next
+    /// piece can be chosen.
+    Zen,
+    /// Practice like `Zen`, counting the moves and rotations spent on each piece and flagging the
+    /// placements that took more than the fewest possible.
+    Finesse,
+    /// Starts from an authored map and ends as soon as the `goal` is reached, or fails once
+    /// `pieces` pieces locked without reaching it.
+    Puzzle { goal: PuzzleGoal, pieces: u32 },
+}
+
+impl GameMode {
+    pub fn is_goal_reached(&self, state: &GameState) -> bool {
+        match *self {
+            GameMode::Endless => false,
+            GameMode::Marathon { lines } => state.lines_cleared >= lines,
+            GameMode::Sprint { lines } => state.lines_cleared >= lines,
+            GameMode::Ultra { .. } => false,
+            GameMode::Dig { .. } => state.garbage_lines_cleared >= self.initial_garbage_rows(state.map.height),
+            GameMode::Survival { .. } => false,
+            GameMode::Master => state.lines_cleared >= MASTER_LINES,
+            GameMode::Zen | GameMode::Finesse => false,
+            GameMode::Puzzle { goal, .. } => match goal {
+                PuzzleGoal::Lines(lines) => state.lines_cleared >= lines,
+                PuzzleGoal::PerfectClear => state.perfect_clears > 0,
+                PuzzleGoal::TSpinDouble => state.tspin_doubles > 0,
+            },
+        }
+    }
+
+    /// Rows of garbage the map starts with, leaving `GARBAGE_FREE_ROWS` empty at the top.
+    pub fn initial_garbage_rows(&self, height: u16) -> u32 {
+        match *self {
+            GameMode::Dig { rows } => rows.min(height.saturating_sub(GARBAGE_FREE_ROWS) as u32),
+            _ => 0,
+        }
+    }
+
+    /// Game time at which the game ends with its goal reached.
+    pub fn time_limit_millis(&self) -> Option<u128> {
+        match *self {
+            GameMode::Ultra { duration_millis } => Some(duration_millis),
+            _ => None,
+        }
+    }
+
+    /// Time between the garbage row number `rows_added` and the next one.
+    pub fn garbage_interval_millis(&self, rows_added: u32) -> Option<u128> {
+        let mut interval_millis = match *self {
+            GameMode::Survival { interval_millis } => interval_millis,
+            _ => return None,
+        };
+
+        for _ in 0..rows_added {
+            if interval_millis <= MIN_SURVIVAL_INTERVAL_MILLIS {
+                break;
+            }
+            interval_millis = interval_millis * 9 / 10;
+        }
+
+        Some(interval_millis.max(MIN_SURVIVAL_INTERVAL_MILLIS))
+    }
+
+    /// Speeds that replace the settings and the gravity curve at a level, if the mode has its own.
+    pub fn section_timing(&self, level: u32) -> Option<&'static SectionTiming> {
+        match self {
+            GameMode::Master => MASTER_SECTIONS.get(level.max(1) as usize - 1).or(MASTER_SECTIONS.last()),
+            _ => None,
+        }
+    }
+
+    /// Number of pieces that can lock before the game fails.
+    pub fn piece_limit(&self) -> Option<u32> {
+        match *self {
+            GameMode::Puzzle { pieces, .. } => Some(pieces),
+            _ => None,
+        }
+    }
+
+    /// Whether topping out is how games of this mode end, with a result, rather than a failure.
+    pub fn ends_by_topping_out(&self) -> bool {
+        matches!(self, GameMode::Survival { .. })
+    }
+
+    /// The highest level the game can reach, which is the one the goal is reached on.
+    pub fn max_level(&self) -> Option<u32> {
+        match *self {
+            GameMode::Marathon { lines } => Some((lines / LINES_PER_LEVEL).max(1)),
+            GameMode::Master => Some(MASTER_SECTIONS.len() as u32),
+            _ => None,
+        }
+    }
+
+    pub fn is_practice(&self) -> bool {
+        matches!(self, GameMode::Zen | GameMode::Finesse)
+    }
+
+    pub fn has_level_gravity(&self) -> bool {
+        !matches!(self, GameMode::Ultra { .. })
+    }
+
+    /// Name and parameter that identify the mode in save and replay files.
+    pub fn name(&self) -> &'static str {
+        match self {
+            GameMode::Endless => "endless",
+            GameMode::Marathon { .. } => "marathon",
+            GameMode::Sprint { .. } => "sprint",
+            GameMode::Ultra { .. } => "ultra",
+            GameMode::Dig { .. } => "dig",
+            GameMode::Survival { .. } => "survival",
+            GameMode::Master => "master",
+            GameMode::Zen => "zen",
+            GameMode::Finesse => "finesse",
+            GameMode::Puzzle { .. } => "puzzle",
+        }
+    }
+
+    /// The parameter of a puzzle packs the number of pieces in the lowest 32 bits, the kind of goal
+    /// in the next 8 and the number of lines of the goal above them.
+    pub fn parameter(&self) -> u128 {
+        match *self {
+            GameMode::Endless => 0,
+            GameMode::Marathon { lines } => lines as u128,
+            GameMode::Sprint { lines } => lines as u128,
+            GameMode::Ultra { duration_millis } => duration_millis,
+            GameMode::Dig { rows } => rows as u128,
+            GameMode::Survival { interval_millis } => interval_millis,
+            GameMode::Master | GameMode::Zen | GameMode::Finesse => 0,
+            GameMode::Puzzle { goal, pieces } => {
+                let (goal_code, lines) = match goal {
+                    PuzzleGoal::Lines(lines) => (0, lines),
+                    PuzzleGoal::PerfectClear => (1, 0),
+                    PuzzleGoal::TSpinDouble => (2, 0),
+                };
+                pieces as u128 | goal_code << 32 | (lines as u128) << 40
+            }
+        }
+    }
+
+    pub fn from_name(name: &str, parameter: u128) -> Option<GameMode> {
+        match name {
+            "endless" => Some(GameMode::Endless),
+            "marathon" => Some(GameMode::Marathon { lines: parameter as u32 }),
+            "sprint" => Some(GameMode::Sprint { lines: parameter as u32 }),
+            "ultra" => Some(GameMode::Ultra { duration_millis: parameter }),
+            "dig" => Some(GameMode::Dig { rows: parameter as u32 }),
+            "survival" => Some(GameMode::Survival { interval_millis: parameter }),
+            "master" => Some(GameMode::Master),
+            "zen" => Some(GameMode::Zen),
+            "finesse" => Some(GameMode::Finesse),
+            "puzzle" => Some(GameMode::Puzzle {
+                goal: match (parameter >> 32) & 0xFF {
+                    0 => PuzzleGoal::Lines((parameter >> 40) as u32),
+                    1 => PuzzleGoal::PerfectClear,
+                    2 => PuzzleGoal::TSpinDouble,
+                    _ => return None,
+                },
+                pieces: parameter as u32,
+            }),
+            _ => None,
+        }
+    }
+}
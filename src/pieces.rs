@@ -1,8 +1,52 @@
 use crate::tiles::*;
+use crate::game::SPAWN_ROWS_ABOVE_VISIBLE;
 use rand::seq::SliceRandom;
 
-#[derive(Clone)]
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum PieceKind {
+    I,
+    J,
+    L,
+    O,
+    S,
+    Z,
+    T,
+}
+
+impl PieceKind {
+    pub fn to_char(self) -> char {
+        match self {
+            PieceKind::I => 'I',
+            PieceKind::J => 'J',
+            PieceKind::L => 'L',
+            PieceKind::O => 'O',
+            PieceKind::S => 'S',
+            PieceKind::Z => 'Z',
+            PieceKind::T => 'T',
+        }
+    }
+
+    pub fn from_char(c: char) -> Option<PieceKind> {
+        match c.to_ascii_uppercase() {
+            'I' => Some(PieceKind::I),
+            'J' => Some(PieceKind::J),
+            'L' => Some(PieceKind::L),
+            'O' => Some(PieceKind::O),
+            'S' => Some(PieceKind::S),
+            'Z' => Some(PieceKind::Z),
+            'T' => Some(PieceKind::T),
+            _ => None
+        }
+    }
+
+    pub fn piece_type(self) -> &'static PieceType {
+        ALL_PIECES.iter().find(|piece_type| piece_type.kind == self).unwrap()
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
 pub struct Piece {
+    pub kind: PieceKind,
     pub tiles: Vec<Tile>,
     pub origin: Tile,
     pub bounding_box_size: i16,
@@ -10,14 +54,16 @@ pub struct Piece {
 }
 
 pub struct PieceType {
+    pub kind: PieceKind,
     pub tiles: [Tile; 4],
     pub origin: Tile,
     pub bounding_box_size: i16,
 }
 
 impl PieceType {
-    const fn new(tiles: [Tile; 4], origin: Tile, bounding_box_size: i16) -> PieceType {
+    const fn new(kind: PieceKind, tiles: [Tile; 4], origin: Tile, bounding_box_size: i16) -> PieceType {
         PieceType {
+            kind,
             tiles,
             origin,
             bounding_box_size,
@@ -25,6 +71,9 @@ impl PieceType {
     }
 }
 
+/// Index of the last test in each row of the kick tables.
+pub const LAST_KICK_TEST_INDEX: usize = 3;
+
 pub static SIZE_3_KICK_TESTS: [[Tile; 4]; 8] = [
     [Tile::new(-1, 0), Tile::new(-1, -1), Tile::new(0, 2), Tile::new(-1, 2)],
     [Tile::new(1, 0), Tile::new(1, 1), Tile::new(0, -2), Tile::new(1, -2)],
@@ -50,6 +99,7 @@ pub static SIZE_4_KICK_TESTS: [[Tile; 4]; 8] = [
 pub static ALL_PIECES: [PieceType; 7] = [
     //Plank
     PieceType::new(
+        PieceKind::I,
         [
             Tile::new(0, 1),
             Tile::new(1, 1),
@@ -60,6 +110,7 @@ pub static ALL_PIECES: [PieceType; 7] = [
         4),
     //J
     PieceType::new(
+        PieceKind::J,
         [
             Tile::new(0, 0),
             Tile::new(0, 1),
@@ -70,6 +121,7 @@ pub static ALL_PIECES: [PieceType; 7] = [
         3),
     //L
     PieceType::new(
+        PieceKind::L,
         [
             Tile::new(2, 0),
             Tile::new(0, 1),
@@ -79,6 +131,7 @@ pub static ALL_PIECES: [PieceType; 7] = [
         3),
     //SQUARE
     PieceType::new(
+        PieceKind::O,
         [
             Tile::new(0, 0),
             Tile::new(1, 0),
@@ -88,6 +141,7 @@ pub static ALL_PIECES: [PieceType; 7] = [
         2),
     //S
     PieceType::new(
+        PieceKind::S,
         [
             Tile::new(0, 1),
             Tile::new(1, 1),
@@ -97,6 +151,7 @@ pub static ALL_PIECES: [PieceType; 7] = [
         3),
     //Z
     PieceType::new(
+        PieceKind::Z,
         [
             Tile::new(0, 0),
             Tile::new(1, 0),
@@ -106,6 +161,7 @@ pub static ALL_PIECES: [PieceType; 7] = [
         3),
     //T
     PieceType::new(
+        PieceKind::T,
         [
             Tile::new(1, 0),
             Tile::new(0, 1),
@@ -115,25 +171,113 @@ pub static ALL_PIECES: [PieceType; 7] = [
         3)
 ];
 
+/// Creates a piece of the given type at its spawn position at the top of the map.
+pub fn spawn_piece_of_type(piece_type: &PieceType, width: u16, buffer_height: u16) -> Piece {
+    let mut tiles = piece_type.tiles.to_vec();
+    let start_x = width as i16 / 2 - (piece_type.bounding_box_size as f32 / 2f32).ceil() as i16;
+    let start_y = -(buffer_height.min(SPAWN_ROWS_ABOVE_VISIBLE) as i16);
+    let start = Tile::new(start_x, start_y);
+
+    for tile in &mut tiles {
+        *tile += start;
+    }
+
+    Piece {
+        kind: piece_type.kind,
+        tiles,
+        origin: piece_type.origin + start,
+        bounding_box_size: piece_type.bounding_box_size,
+        rotation_index: 0,
+    }
+}
+
 pub trait PieceTypeSelector {
-    fn select_piece_type<'a>(&self, available_piece_types: &'a[PieceType; 7]) -> &'a PieceType;
+    fn select_piece_type<'a>(&mut self, available_piece_types: &'a[PieceType; 7]) -> &'a PieceType;
 }
 
 pub struct RandomPieceTypeSelector {}
 
 impl PieceTypeSelector for RandomPieceTypeSelector {
-    fn select_piece_type<'a>(&self, available_piece_types: &'a[PieceType; 7]) -> &'a PieceType {
+    fn select_piece_type<'a>(&mut self, available_piece_types: &'a[PieceType; 7]) -> &'a PieceType {
         let mut rng = rand::thread_rng();
         available_piece_types.choose(&mut rng).unwrap()
     }
 }
 
+#[derive(Clone)]
 pub struct ManualPieceTypeSelector {
     pub piece_index: usize
 }
 
 impl PieceTypeSelector for ManualPieceTypeSelector {
-    fn select_piece_type<'a>(&self, available_piece_types: &'a [PieceType; 7]) -> &'a PieceType {
+    fn select_piece_type<'a>(&mut self, available_piece_types: &'a [PieceType; 7]) -> &'a PieceType {
         &available_piece_types[self.piece_index]
     }
-}
\ No newline at end of file
+}
+
+/// Deals the pieces of a fixed sequence in order, starting over once it runs out. The sequence
+/// must not be empty.
+#[derive(Clone, Default)]
+pub struct SequencePieceTypeSelector {
+    pub sequence: Vec<PieceKind>,
+    /// Index in `sequence` of the next piece to deal.
+    pub next_index: usize,
+}
+
+impl SequencePieceTypeSelector {
+    pub fn new(sequence: Vec<PieceKind>) -> SequencePieceTypeSelector {
+        SequencePieceTypeSelector { sequence, next_index: 0 }
+    }
+
+    /// Pieces of the sequence that haven't been dealt yet.
+    pub fn remaining(&self) -> &[PieceKind] {
+        &self.sequence[self.next_index.min(self.sequence.len())..]
+    }
+}
+
+impl PieceTypeSelector for SequencePieceTypeSelector {
+    fn select_piece_type<'a>(&mut self, available_piece_types: &'a [PieceType; 7]) -> &'a PieceType {
+        if self.next_index >= self.sequence.len() {
+            self.next_index = 0;
+        }
+
+        let kind = self.sequence[self.next_index];
+        self.next_index += 1;
+        available_piece_types.iter().find(|piece_type| piece_type.kind == kind).unwrap()
+    }
+}
+
+/// Deals pieces from a small deterministic generator, so the same seed always produces the same
+/// piece sequence on every platform.
+#[derive(Clone)]
+pub struct SeededPieceTypeSelector {
+    /// The seed the sequence started from, which replays the whole sequence.
+    pub seed: u64,
+    pub rng_state: u64
+}
+
+impl SeededPieceTypeSelector {
+    pub fn new(seed: u64) -> SeededPieceTypeSelector {
+        SeededPieceTypeSelector { seed, rng_state: seed }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        split_mix64(&mut self.rng_state)
+    }
+}
+
+/// Advances a SplitMix64 generator and returns its next value.
+pub fn split_mix64(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+impl PieceTypeSelector for SeededPieceTypeSelector {
+    fn select_piece_type<'a>(&mut self, available_piece_types: &'a [PieceType; 7]) -> &'a PieceType {
+        let index = self.next_u64() % available_piece_types.len() as u64;
+        &available_piece_types[index as usize]
+    }
+}
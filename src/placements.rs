@@ -0,0 +1,85 @@
+use std::collections::{HashSet, VecDeque};
+use crate::tiles::Tile;
+use crate::game::*;
+use crate::pieces::*;
+use crate::input::InputResult;
+
+/// Inputs that move the falling piece without locking it.
+const PLACEMENT_INPUTS: [InputResult; 5] = [
+    InputResult::MoveLeft,
+    InputResult::MoveRight,
+    InputResult::MoveDown,
+    InputResult::RotateClockwise,
+    InputResult::RotateCounterClockwise,
+];
+
+/// A position where the falling piece rests on the stack or the floor, and the inputs that bring
+/// it there. One more `MoveDown` locks it.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Placement {
+    pub piece: Piece,
+    pub inputs: Vec<InputResult>,
+    /// Whether the last input was a rotation and which kick it used, like
+    /// `Game::last_rotation_kick`. Only T pieces keep it, as only they spin.
+    pub last_rotation_kick: Option<Option<usize>>,
+}
+
+/// Every position where the falling piece of `state` can come to rest through moves, rotations
+/// with their wall kicks and soft drops, including tucks and spins, with the fewest inputs that
+/// reach each one from where the piece is. Gravity is left out, as if the inputs came quickly
+/// enough. T pieces that can rest in the same position after a move and after a rotation, or
+/// after rotations with different kicks, get a placement for each, since they score differently.
+/// Outside of `Phase::Falling` the piece has already locked, so there are none.
+pub fn reachable_placements(state: &GameState) -> Vec<Placement> {
+    if state.phase != Phase::Falling {
+        return Vec::new();
+    }
+
+    let mut state = state.clone();
+    let start = state.falling_piece.clone();
+    let mut visited = HashSet::new();
+    visited.insert((start.origin, start.rotation_index, None));
+    let mut queue = VecDeque::new();
+    queue.push_back((start, None, Vec::new()));
+    let mut placements = Vec::new();
+
+    while let Some((piece, last_rotation_kick, inputs)) = queue.pop_front() {
+        state.falling_piece = piece.clone();
+        for input in &PLACEMENT_INPUTS {
+            let next = match input {
+                InputResult::MoveLeft if can_move_left(&mut state) => Some((moved(&piece, Tile::new(-1, 0)), None)),
+                InputResult::MoveRight if can_move_right(&mut state) => Some((moved(&piece, Tile::new(1, 0)), None)),
+                InputResult::MoveDown if can_move_down(&state) => Some((moved(&piece, Tile::new(0, 1)), None)),
+                InputResult::RotateClockwise => rotate_with_kicks(&mut state, true)
+                    .map(|(rotated_piece, kick)| (rotated_piece, Some(kick))),
+                InputResult::RotateCounterClockwise => rotate_with_kicks(&mut state, false)
+                    .map(|(rotated_piece, kick)| (rotated_piece, Some(kick))),
+                _ => None,
+            };
+
+            if let Some((next_piece, next_kick)) = next {
+                let next_kick = next_kick.filter(|_| next_piece.kind == PieceKind::T);
+                if visited.insert((next_piece.origin, next_piece.rotation_index, next_kick)) {
+                    let mut next_inputs = inputs.clone();
+                    next_inputs.push(*input);
+                    queue.push_back((next_piece, next_kick, next_inputs));
+                }
+            }
+        }
+
+        if !can_move_down(&state) {
+            placements.push(Placement { piece, inputs, last_rotation_kick });
+        }
+    }
+
+    placements
+}
+
+fn moved(piece: &Piece, delta: Tile) -> Piece {
+    let mut piece = piece.clone();
+    for tile in &mut piece.tiles {
+        *tile += delta;
+    }
+    piece.origin += delta;
+    piece
+}
@@ -0,0 +1,264 @@
+use std::io;
+use std::cell::RefCell;
+use std::rc::Rc;
+use crate::game::*;
+use crate::pieces::SeededPieceTypeSelector;
+use crate::input::*;
+use crate::time::*;
+use crate::drawing::NoopDrawing;
+use crate::events::GameEvent;
+use crate::builder::GameBuilder;
+use crate::replay::*;
+
+/// Game time covered by a single frame step.
+pub const FRAME_MILLIS: u128 = 16;
+pub const PLAYBACK_SPEEDS: [f64; 6] = [0.25, 0.5, 1.0, 2.0, 4.0, 8.0];
+const NORMAL_SPEED_INDEX: usize = 2;
+
+/// Position in a replay, shared by its input source and its clock.
+struct ReplayCursor {
+    inputs: Vec<RecordedInput>,
+    next_input: usize,
+    time_millis: u128,
+}
+
+impl ReplayCursor {
+    fn next_input_millis(&self) -> Option<u128> {
+        self.inputs.get(self.next_input).map(|input| input.time_millis)
+    }
+}
+
+/// Returns each recorded input once the replay clock reaches the time it was recorded at.
+pub struct ReplayInput {
+    cursor: Rc<RefCell<ReplayCursor>>
+}
+
+impl InputSource for ReplayInput {
+    fn read_input(&mut self) -> Option<InputResult> {
+        let mut cursor = self.cursor.borrow_mut();
+        let recorded_input = *cursor.inputs.get(cursor.next_input)?;
+        if recorded_input.time_millis > cursor.time_millis {
+            return None;
+        }
+
+        cursor.next_input += 1;
+        Some(recorded_input.input)
+    }
+}
+
+/// The time of a replay, only moved by `ReplayClock::advance`.
+pub struct ReplayClock {
+    cursor: Rc<RefCell<ReplayCursor>>
+}
+
+impl ReplayClock {
+    /// Moves the time forward by up to `millis`, stopping at the next pending input so every input
+    /// is read by a frame at exactly its recorded time. Returns how far the time moved.
+    pub fn advance(&self, millis: u128) -> u128 {
+        let mut cursor = self.cursor.borrow_mut();
+        let target_millis = match cursor.next_input_millis() {
+            Some(input_millis) => (cursor.time_millis + millis).min(input_millis.max(cursor.time_millis)),
+            None => cursor.time_millis + millis,
+        };
+
+        let advanced_millis = target_millis - cursor.time_millis;
+        cursor.time_millis = target_millis;
+        advanced_millis
+    }
+}
+
+impl Clock for ReplayClock {
+    type Instant = ManualClockInstant;
+
+    fn now(&self) -> ManualClockInstant {
+        ManualClock { now_milliseconds: self.cursor.borrow().time_millis }.now()
+    }
+}
+
+/// Creates the input source and clock that play a replay back through a game.
+pub fn replay_input_and_clock(replay: &Replay) -> (ReplayInput, ReplayClock) {
+    let cursor = Rc::new(RefCell::new(ReplayCursor {
+        inputs: replay.inputs.clone(),
+        next_input: 0,
+        time_millis: 0,
+    }));
+
+    (ReplayInput { cursor: cursor.clone() }, ReplayClock { cursor })
+}
+
+type PlaybackGame = Game<ReplayInput, SeededPieceTypeSelector, ReplayClock, NoopDrawing>;
+
+/// Plays a replay back through the engine, with pause, speed control, frame stepping and seeking.
+/// Nothing is drawn, so frontends draw `state()` themselves.
+pub struct Playback {
+    replay: Replay,
+    game: PlaybackGame,
+    /// Number of the falling piece, starting at 1.
+    piece_number: usize,
+    finished: bool,
+    speed_index: usize,
+    /// Game time owed by previous updates that was too short to play, in milliseconds.
+    pending_millis: f64,
+    pub paused: bool,
+}
+
+impl Playback {
+    /// Starts playing a replay from its beginning, or fails if its settings aren't valid.
+    pub fn new(replay: Replay) -> io::Result<Playback> {
+        Ok(Playback {
+            game: Playback::create_game(&replay)?,
+            replay,
+            piece_number: 1,
+            finished: false,
+            speed_index: NORMAL_SPEED_INDEX,
+            pending_millis: 0.0,
+            paused: false,
+        })
+    }
+
+    fn create_game(replay: &Replay) -> io::Result<PlaybackGame> {
+        let (input, clock) = replay_input_and_clock(replay);
+        GameBuilder::new()
+            .input(input)
+            .piece_type_selector(SeededPieceTypeSelector::new(replay.seed))
+            .clock(clock)
+            .settings(replay.settings)
+            .build()
+    }
+
+    pub fn state(&self) -> &GameState {
+        &self.game.state
+    }
+
+    pub fn replay(&self) -> &Replay {
+        &self.replay
+    }
+
+    pub fn time_millis(&self) -> u128 {
+        self.game.state.time_millis
+    }
+
+    pub fn piece_number(&self) -> usize {
+        self.piece_number
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    pub fn speed(&self) -> f64 {
+        PLAYBACK_SPEEDS[self.speed_index]
+    }
+
+    pub fn speed_up(&mut self) {
+        self.speed_index = (self.speed_index + 1).min(PLAYBACK_SPEEDS.len() - 1);
+    }
+
+    pub fn slow_down(&mut self) {
+        self.speed_index = self.speed_index.saturating_sub(1);
+    }
+
+    /// Whether playback reached the end of the replay with the same board the recorded game ended
+    /// with.
+    pub fn matches_recording(&self) -> bool {
+        self.finished && board_checksum(&self.game.state.map) == self.replay.board_checksum
+    }
+
+    /// Plays the real time that passed since the last update, scaled by the playback speed.
+    pub fn update(&mut self, real_elapsed_millis: u128) {
+        if self.paused {
+            return;
+        }
+
+        self.pending_millis += real_elapsed_millis as f64 * self.speed();
+        let millis = self.pending_millis.floor();
+        self.pending_millis -= millis;
+        self.advance(millis as u128);
+    }
+
+    pub fn step_frame(&mut self) {
+        self.advance(FRAME_MILLIS);
+    }
+
+    /// Plays `millis` of game time, running a frame at the time of each recorded input and at the
+    /// end.
+    pub fn advance(&mut self, millis: u128) {
+        let end_millis = self.replay.final_time_millis.min(self.time_millis() + millis);
+        while !self.finished {
+            let remaining_millis = end_millis.saturating_sub(self.game.now_millis());
+            let advanced_millis = self.game.clock.advance(remaining_millis);
+            self.run_frame();
+
+            if advanced_millis == remaining_millis && !self.has_input_due() {
+                break;
+            }
+        }
+    }
+
+    /// Plays up to the time of the next recorded input, or to the end of the replay, in a single
+    /// frame and returns the events it emitted. Returns nothing once playback is finished.
+    pub fn next_frame(&mut self) -> &[GameEvent] {
+        if self.finished {
+            return &[];
+        }
+
+        let remaining_millis = self.replay.final_time_millis.saturating_sub(self.game.now_millis());
+        self.game.clock.advance(remaining_millis);
+        self.run_frame();
+        &self.game.events
+    }
+
+    fn has_input_due(&self) -> bool {
+        let cursor = self.game.clock.cursor.borrow();
+        matches!(cursor.next_input_millis(), Some(input_millis) if input_millis <= cursor.time_millis)
+    }
+
+    fn run_frame(&mut self) {
+        let result = self.game.run_frame();
+        self.piece_number += self.game.events.iter()
+            .filter(|event| matches!(event, GameEvent::PieceSpawned { .. }))
+            .count();
+
+        let is_at_end = self.game.now_millis() >= self.replay.final_time_millis && !self.has_input_due();
+        if result != FrameResult::GameInProgress || is_at_end {
+            self.finished = true;
+        }
+    }
+
+    /// Moves playback to the moment the given piece spawned, or to the end if the game ended
+    /// before it. Seeking backwards plays the replay again from the start.
+    pub fn seek_to_piece(&mut self, piece_number: usize) {
+        if piece_number < self.piece_number {
+            self.game = Playback::create_game(&self.replay).expect("the settings were checked by Playback::new");
+            self.piece_number = 1;
+            self.finished = false;
+        }
+
+        while self.piece_number < piece_number && !self.finished {
+            self.step_frame();
+        }
+
+        self.pending_millis = 0.0;
+    }
+
+    /// Plays the rest of the replay at once.
+    pub fn finish(&mut self) {
+        while !self.finished {
+            self.advance(self.replay.final_time_millis);
+        }
+    }
+}
+
+impl Replay {
+    /// Plays the whole replay back and checks that it ends with the recorded board. Replays with
+    /// invalid settings don't verify.
+    pub fn verify(&self) -> bool {
+        match Playback::new(self.clone()) {
+            Ok(mut playback) => {
+                playback.finish();
+                playback.matches_recording()
+            }
+            Err(_) => false,
+        }
+    }
+}
@@ -0,0 +1,149 @@
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use crate::game::*;
+use crate::pieces::*;
+use crate::modes::{GameMode, PuzzleGoal};
+use crate::board::Board;
+
+pub const PUZZLE_EXTENSION: &str = "puzzle";
+/// The example pack in this repository's `puzzles` directory, built in so that it can be browsed
+/// wherever the game runs. Each puzzle is given by its file name and contents.
+pub const SHIPPED_PUZZLES: [(&str, &str); 3] = [
+    ("01-tetris.puzzle", include_str!("../puzzles/01-tetris.puzzle")),
+    ("02-square-finish.puzzle", include_str!("../puzzles/02-square-finish.puzzle")),
+    ("03-two-pieces.puzzle", include_str!("../puzzles/03-two-pieces.puzzle")),
+];
+
+/// A starting map, the fixed sequence of pieces to play on it and the goal to reach with them.
+///
+/// Puzzles are written as text. Above the board, lines starting with `#` are comments, and the
+/// others give the name, the goal (`lines <count>`, `perfect-clear` or `tspin-double`), the pieces and then the
+/// rows of the board, the last one at the bottom of the map:
+///
+/// ```text
+/// name Tuck the T
+/// goal tspin-double
+/// queue T
+/// board
+/// ##...#####
+/// ###.######
+/// ```
+///
+/// The board is written like a `Board`, as wide as its rows and without a falling piece.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Puzzle {
+    pub name: String,
+    pub goal: PuzzleGoal,
+    pub queue: Vec<PieceKind>,
+    pub board: Board,
+}
+
+impl Puzzle {
+    pub fn load_from(path: &Path) -> io::Result<Puzzle> {
+        Puzzle::read(BufReader::new(File::open(path)?))
+    }
+
+    pub fn read<R: BufRead>(reader: R) -> io::Result<Puzzle> {
+        let mut name = None;
+        let mut goal = None;
+        let mut queue = None;
+        let mut lines = reader.lines()
+            .filter(|line| !matches!(line, Ok(line) if line.trim().is_empty()));
+
+        for line in &mut lines {
+            let line = line?;
+            if line.starts_with('#') {
+                continue;
+            }
+
+            let (key, value) = match line.split_once(' ') {
+                Some((key, value)) => (key, value.trim()),
+                None => (line.trim(), ""),
+            };
+
+            match key {
+                "name" => name = Some(value.to_string()),
+                "goal" => goal = Some(parse_goal(value)?),
+                "queue" => queue = Some(value.chars()
+                    .filter(|c| !c.is_whitespace())
+                    .map(|c| PieceKind::from_char(c).ok_or_else(|| invalid_data(&format!("invalid piece {}", c))))
+                    .collect::<io::Result<Vec<_>>>()?),
+                "board" => break,
+                _ => return Err(invalid_data(&format!("unknown puzzle field {}", key))),
+            }
+        }
+
+        let rows = lines.collect::<io::Result<Vec<String>>>()?;
+        let settings = GameSettings {
+            width: rows.first().map_or(0, |row| row.trim().chars().count() as u16),
+            ..GameSettings::default()
+        };
+        settings.validate()?;
+        if rows.len() > settings.height as usize {
+            return Err(invalid_data("board is too tall"));
+        }
+        let board = Board::parse_for(&rows.join("\n"), &settings)?;
+        if board.falling_piece.is_some() {
+            return Err(invalid_data("board can't have a falling piece"));
+        }
+
+        Ok(Puzzle {
+            name: name.ok_or_else(|| invalid_data("missing name"))?,
+            goal: goal.ok_or_else(|| invalid_data("missing goal"))?,
+            queue: queue.filter(|queue| !queue.is_empty()).ok_or_else(|| invalid_data("missing queue"))?,
+            board,
+        })
+    }
+
+    /// Settings of a game of this puzzle, on a map as wide as the board.
+    pub fn settings(&self) -> GameSettings {
+        GameSettings {
+            width: self.board.map.width,
+            mode: GameMode::Puzzle { goal: self.goal, pieces: self.queue.len() as u32 },
+            ..GameSettings::default()
+        }
+    }
+
+    pub fn piece_type_selector(&self) -> SequencePieceTypeSelector {
+        SequencePieceTypeSelector::new(self.queue.clone())
+    }
+
+    /// The map a game of this puzzle starts with, with the board at the bottom.
+    pub fn map(&self) -> Map {
+        self.board.map.clone()
+    }
+}
+
+/// Loads every puzzle of a pack, which is a directory of `.puzzle` files, sorted by file name.
+/// Files that can't be loaded are listed with their error.
+pub fn load_puzzle_pack(directory: &Path) -> io::Result<Vec<(PathBuf, io::Result<Puzzle>)>> {
+    let mut paths: Vec<PathBuf> = fs::read_dir(directory)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|extension| extension == PUZZLE_EXTENSION))
+        .collect();
+    paths.sort();
+
+    Ok(paths.into_iter()
+        .map(|path| {
+            let puzzle = Puzzle::load_from(&path);
+            (path, puzzle)
+        })
+        .collect())
+}
+
+/// The shipped puzzles as a pack, like `load_puzzle_pack` would load them from the directory.
+pub fn shipped_puzzle_pack() -> Vec<(PathBuf, io::Result<Puzzle>)> {
+    SHIPPED_PUZZLES.iter()
+        .map(|(file_name, text)| (PathBuf::from(file_name), Puzzle::read(text.as_bytes())))
+        .collect()
+}
+
+fn parse_goal(value: &str) -> io::Result<PuzzleGoal> {
+    match value.split_whitespace().collect::<Vec<_>>().as_slice() {
+        ["lines", count] => count.parse().map(PuzzleGoal::Lines).map_err(|_| invalid_data("invalid line count")),
+        ["perfect-clear"] => Ok(PuzzleGoal::PerfectClear),
+        ["tspin-double"] => Ok(PuzzleGoal::TSpinDouble),
+        _ => Err(invalid_data(&format!("invalid goal {}", value))),
+    }
+}
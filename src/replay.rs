@@ -0,0 +1,244 @@
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use crate::game::*;
+use crate::tiles::*;
+use crate::pieces::*;
+use crate::modes::GameMode;
+use crate::input::*;
+use crate::time::Clock;
+use crate::drawing::Drawing;
+
+pub const REPLAY_VERSION: u8 = 5;
+const REPLAY_MAGIC: &[u8; 4] = b"TTRP";
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct RecordedInput {
+    /// Game time at which the input was read.
+    pub time_millis: u128,
+    pub input: InputResult,
+}
+
+/// A recorded game: the seed and settings it started with and every input it read. Since the
+/// engine is deterministic, this is enough to play the whole game back. The final time and board
+/// checksum are filled in when the recording finishes, to verify playback.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Replay {
+    pub seed: u64,
+    pub settings: GameSettings,
+    pub inputs: Vec<RecordedInput>,
+    pub final_time_millis: u128,
+    pub board_checksum: u64,
+}
+
+impl<I: InputSource, TC: Clock, TD: Drawing> Game<I, SeededPieceTypeSelector, TC, TD> {
+    /// Starts recording every input read from now on. Replays are played back from a new game
+    /// with the selector's original seed, so this should be called before the first frame.
+    pub fn start_recording(&mut self) {
+        self.recording = Some(Replay {
+            seed: self.piece_type_selector.seed,
+            settings: self.settings,
+            inputs: Vec::new(),
+            final_time_millis: 0,
+            board_checksum: 0,
+        });
+    }
+}
+
+impl<I: InputSource, PTS: PieceTypeSelector, TC: Clock, TD: Drawing> Game<I, PTS, TC, TD> {
+    pub fn finish_recording(&mut self) -> Option<Replay> {
+        let mut replay = self.recording.take()?;
+        replay.final_time_millis = self.state.time_millis;
+        replay.board_checksum = board_checksum(&self.state.map);
+        Some(replay)
+    }
+}
+
+/// FNV-1a hash of the board dimensions and of which tiles are set, including the buffer rows.
+pub fn board_checksum(map: &Map) -> u64 {
+    let mut hash: u64 = 0xCBF2_9CE4_8422_2325;
+    let mut add_byte = |byte: u8| {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x0000_0100_0000_01B3);
+    };
+
+    for value in &[map.width, map.height, map.buffer_height] {
+        value.to_le_bytes().iter().for_each(|byte| add_byte(*byte));
+    }
+
+    for y in -(map.buffer_height as i16)..map.height as i16 {
+        for x in 0..map.width as i16 {
+            add_byte(map[Tile::new(x, y)].is_set as u8);
+        }
+    }
+
+    hash
+}
+
+impl Replay {
+    pub fn save_to(&self, path: &Path) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write(&mut writer)?;
+        writer.flush()
+    }
+
+    pub fn load_from(path: &Path) -> io::Result<Replay> {
+        Replay::read(&mut BufReader::new(File::open(path)?))
+    }
+
+    /// Writes the replay in a compact binary format. Each input takes a single varint holding the
+    /// time since the previous input and the input kind.
+    pub fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let settings = &self.settings;
+
+        writer.write_all(REPLAY_MAGIC)?;
+        writer.write_all(&[REPLAY_VERSION])?;
+        writer.write_all(&self.seed.to_le_bytes())?;
+        writer.write_all(&settings.garbage_seed.to_le_bytes())?;
+        writer.write_all(&settings.width.to_le_bytes())?;
+        writer.write_all(&settings.height.to_le_bytes())?;
+        writer.write_all(&settings.buffer_height.to_le_bytes())?;
+        writer.write_all(&[settings.partial_lock_out as u8])?;
+        writer.write_all(&[settings.big as u8])?;
+        write_varint(writer, settings.line_clear_delay_millis)?;
+        write_varint(writer, settings.spawn_delay_millis)?;
+        let mode_name = settings.mode.name().as_bytes();
+        writer.write_all(&[mode_name.len() as u8])?;
+        writer.write_all(mode_name)?;
+        write_varint(writer, settings.mode.parameter())?;
+        write_varint(writer, self.final_time_millis)?;
+        writer.write_all(&self.board_checksum.to_le_bytes())?;
+
+        write_varint(writer, self.inputs.len() as u128)?;
+        let mut last_time_millis = 0;
+        for recorded_input in &self.inputs {
+            let delta_millis = recorded_input.time_millis - last_time_millis;
+            write_varint(writer, delta_millis << 4 | input_code(recorded_input.input) as u128)?;
+            last_time_millis = recorded_input.time_millis;
+        }
+
+        Ok(())
+    }
+
+    pub fn read<R: Read>(reader: &mut R) -> io::Result<Replay> {
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != REPLAY_MAGIC {
+            return Err(invalid_data("not a replay file"));
+        }
+
+        let version = read_array::<R, 1>(reader)?[0];
+        if version != REPLAY_VERSION {
+            return Err(invalid_data(&format!("unsupported replay version {}", version)));
+        }
+
+        let seed = u64::from_le_bytes(read_array(reader)?);
+        let settings = GameSettings {
+            garbage_seed: u64::from_le_bytes(read_array(reader)?),
+            width: u16::from_le_bytes(read_array(reader)?),
+            height: u16::from_le_bytes(read_array(reader)?),
+            buffer_height: u16::from_le_bytes(read_array(reader)?),
+            partial_lock_out: read_array::<R, 1>(reader)?[0] != 0,
+            big: read_array::<R, 1>(reader)?[0] != 0,
+            line_clear_delay_millis: read_varint(reader)?,
+            spawn_delay_millis: read_varint(reader)?,
+            mode: read_mode(reader)?,
+        };
+        settings.validate()?;
+        let final_time_millis = read_varint(reader)?;
+        let board_checksum = u64::from_le_bytes(read_array(reader)?);
+
+        let count = read_varint(reader)?;
+        let mut inputs = Vec::new();
+        let mut time_millis: u128 = 0;
+        for _ in 0..count {
+            let value = read_varint(reader)?;
+            time_millis = time_millis.checked_add(value >> 4)
+                .ok_or_else(|| invalid_data("input time is too late"))?;
+            inputs.push(RecordedInput {
+                time_millis,
+                input: input_from_code((value & 0b1111) as u8)?,
+            });
+        }
+
+        Ok(Replay {
+            seed,
+            settings,
+            inputs,
+            final_time_millis,
+            board_checksum,
+        })
+    }
+}
+
+fn input_code(input: InputResult) -> u8 {
+    match input {
+        InputResult::MoveLeft => 0,
+        InputResult::MoveRight => 1,
+        InputResult::MoveDown => 2,
+        InputResult::RotateClockwise => 3,
+        InputResult::RotateCounterClockwise => 4,
+        InputResult::ExitGame => 5,
+        InputResult::Undo => 6,
+        InputResult::Redo => 7,
+        InputResult::ChooseNextPiece(kind) => 8 + piece_index(kind) as u8,
+    }
+}
+
+fn input_from_code(code: u8) -> io::Result<InputResult> {
+    match code {
+        0 => Ok(InputResult::MoveLeft),
+        1 => Ok(InputResult::MoveRight),
+        2 => Ok(InputResult::MoveDown),
+        3 => Ok(InputResult::RotateClockwise),
+        4 => Ok(InputResult::RotateCounterClockwise),
+        5 => Ok(InputResult::ExitGame),
+        6 => Ok(InputResult::Undo),
+        7 => Ok(InputResult::Redo),
+        8..=14 => Ok(InputResult::ChooseNextPiece(ALL_PIECES[code as usize - 8].kind)),
+        _ => Err(invalid_data(&format!("invalid input code {}", code))),
+    }
+}
+
+fn piece_index(kind: PieceKind) -> usize {
+    ALL_PIECES.iter().position(|piece_type| piece_type.kind == kind).unwrap()
+}
+
+fn read_mode<R: Read>(reader: &mut R) -> io::Result<GameMode> {
+    let mut name = vec![0u8; read_array::<R, 1>(reader)?[0] as usize];
+    reader.read_exact(&mut name)?;
+    let name = String::from_utf8_lossy(&name);
+    GameMode::from_name(&name, read_varint(reader)?)
+        .ok_or_else(|| invalid_data(&format!("invalid mode {}", name)))
+}
+
+fn write_varint<W: Write>(writer: &mut W, mut value: u128) -> io::Result<()> {
+    loop {
+        let byte = (value & 0x7F) as u8;
+        value >>= 7;
+        if value == 0 {
+            return writer.write_all(&[byte]);
+        }
+
+        writer.write_all(&[byte | 0x80])?;
+    }
+}
+
+fn read_varint<R: Read>(reader: &mut R) -> io::Result<u128> {
+    let mut value = 0u128;
+    for shift in (0..128).step_by(7) {
+        let byte = read_array::<R, 1>(reader)?[0];
+        value |= ((byte & 0x7F) as u128) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+
+    Err(invalid_data("varint is too long"))
+}
+
+fn read_array<R: Read, const N: usize>(reader: &mut R) -> io::Result<[u8; N]> {
+    let mut bytes = [0u8; N];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
@@ -0,0 +1,324 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use crate::game::*;
+use crate::tiles::*;
+use crate::pieces::*;
+use crate::modes::GameMode;
+use crate::input::InputSource;
+use crate::time::Clock;
+use crate::drawing::Drawing;
+use crate::board::{self, Board};
+
+pub const SAVE_VERSION: u32 = 11;
+const SAVE_HEADER: &str = "tetris-save";
+
+/// Everything needed to continue a game later: its settings, its state (including the game time
+/// and timers) and the state of the piece generator.
+#[derive(Clone)]
+pub struct SavedGame {
+    pub settings: GameSettings,
+    pub state: GameState,
+    pub rng_state: u64,
+}
+
+impl<I: InputSource, TC: Clock, TD: Drawing> Game<I, SeededPieceTypeSelector, TC, TD> {
+    pub fn save(&self) -> SavedGame {
+        SavedGame {
+            settings: self.settings,
+            state: self.state.clone(),
+            rng_state: self.piece_type_selector.rng_state,
+        }
+    }
+
+    /// Replaces the current game with a saved one, continuing from the saved game time. Fails
+    /// without changing the game if the saved settings aren't valid.
+    pub fn resume(&mut self, saved_game: SavedGame) -> io::Result<()> {
+        saved_game.settings.validate()?;
+        let finesse = Self::create_finesse_trainer(&saved_game.settings)?;
+        self.time_offset_millis = saved_game.state.time_millis;
+        self.start_instant = self.clock.now();
+        self.settings = saved_game.settings;
+        self.finesse = finesse;
+        self.state = saved_game.state;
+        self.piece_type_selector.rng_state = saved_game.rng_state;
+        self.top_out = None;
+        self.goal_reached = false;
+        self.last_rotation_kick = None;
+        self.events.clear();
+        Ok(())
+    }
+}
+
+impl SavedGame {
+    pub fn save_to(&self, path: &Path) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write(&mut writer)?;
+        writer.flush()
+    }
+
+    pub fn load_from(path: &Path) -> io::Result<SavedGame> {
+        SavedGame::read(BufReader::new(File::open(path)?))
+    }
+
+    pub fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let settings = &self.settings;
+        let state = &self.state;
+        let piece = &state.falling_piece;
+
+        writeln!(writer, "{} {}", SAVE_HEADER, SAVE_VERSION)?;
+        writeln!(writer, "settings {} {} {} {} {} {} {} {}",
+                 settings.width,
+                 settings.height,
+                 settings.buffer_height,
+                 settings.partial_lock_out as u8,
+                 settings.line_clear_delay_millis,
+                 settings.spawn_delay_millis,
+                 settings.garbage_seed,
+                 settings.big as u8)?;
+        writeln!(writer, "mode {} {}", settings.mode.name(), settings.mode.parameter())?;
+        writeln!(writer, "rng {}", self.rng_state)?;
+        writeln!(writer, "time {} {} {}", state.time_millis, state.last_move_millis, state.phase_start_millis)?;
+
+        write!(writer, "lines {}", state.lines_cleared)?;
+        for split_millis in &state.splits_millis {
+            write!(writer, " {}", split_millis)?;
+        }
+        writeln!(writer)?;
+        writeln!(writer, "score {} {}", state.score, state.level)?;
+        writeln!(writer, "garbage {} {} {} {}",
+                 state.garbage_rng_state,
+                 state.garbage_lines_cleared,
+                 state.garbage_rows_added,
+                 state.last_garbage_millis)?;
+        writeln!(writer, "locks {} {} {}", state.pieces_locked, state.perfect_clears, state.tspin_doubles)?;
+
+        match &state.phase {
+            Phase::Falling => writeln!(writer, "phase falling")?,
+            Phase::ClearingLines { lines, duration_millis, .. } => {
+                write!(writer, "phase clearing {}", duration_millis)?;
+                for y in lines {
+                    write!(writer, " {}", y)?;
+                }
+                writeln!(writer)?;
+            }
+            Phase::Spawning => writeln!(writer, "phase spawning")?,
+        }
+
+        write!(writer, "piece {} {} {}", piece.kind.to_char(), piece.rotation_index, format_tile(piece.origin))?;
+        for tile in &piece.tiles {
+            write!(writer, " {}", format_tile(*tile))?;
+        }
+        writeln!(writer)?;
+        writeln!(writer, "next {}", state.next_piece_kind.map_or('-', PieceKind::to_char))?;
+
+        let board = Board { map: state.map.clone(), falling_piece: None }.to_string();
+        writeln!(writer, "map {}", board.lines().count())?;
+        write!(writer, "{}", board)?;
+
+        write!(writer, "set")?;
+        for map_tile in set_tiles(&state.map) {
+            write!(writer, " {}", map_tile.set_millis)?;
+        }
+        writeln!(writer)?;
+
+        Ok(())
+    }
+
+    pub fn read<R: BufRead>(reader: R) -> io::Result<SavedGame> {
+        let mut lines = reader.lines();
+        let mut next_line = move || -> io::Result<String> {
+            lines.next().unwrap_or_else(|| Err(invalid_data("unexpected end of save file")))
+        };
+
+        let header = next_line()?;
+        let version = match header.split_whitespace().collect::<Vec<_>>().as_slice() {
+            [SAVE_HEADER, version] => parse::<u32>(version)?,
+            _ => return Err(invalid_data("not a save file")),
+        };
+        if version != SAVE_VERSION {
+            return Err(invalid_data(&format!("unsupported save file version {}", version)));
+        }
+
+        let values = fields(&next_line()?, "settings", 8)?;
+        let mode_values = fields(&next_line()?, "mode", 2)?;
+        let settings = GameSettings {
+            width: parse(&values[0])?,
+            height: parse(&values[1])?,
+            buffer_height: parse(&values[2])?,
+            partial_lock_out: parse::<u8>(&values[3])? != 0,
+            line_clear_delay_millis: parse(&values[4])?,
+            spawn_delay_millis: parse(&values[5])?,
+            garbage_seed: parse(&values[6])?,
+            big: parse::<u8>(&values[7])? != 0,
+            mode: GameMode::from_name(&mode_values[0], parse(&mode_values[1])?)
+                .ok_or_else(|| invalid_data(&format!("invalid mode {}", mode_values[0])))?,
+        };
+        settings.validate()?;
+
+        let rng_state = parse(&fields(&next_line()?, "rng", 1)?[0])?;
+
+        let values = fields(&next_line()?, "time", 3)?;
+        let time_millis: u128 = parse(&values[0])?;
+        let last_move_millis = parse(&values[1])?;
+        let phase_start_millis: u128 = parse(&values[2])?;
+
+        let lines_line = next_line()?;
+        let values: Vec<&str> = lines_line.split_whitespace().collect();
+        let (lines_cleared, splits_millis) = match values.as_slice() {
+            ["lines", count, splits @ ..] => (
+                parse(count)?,
+                splits.iter().map(|split| parse(split)).collect::<io::Result<_>>()?,
+            ),
+            _ => return Err(invalid_data("expected lines")),
+        };
+
+        let values = fields(&next_line()?, "score", 2)?;
+        let score = parse(&values[0])?;
+        let level = parse(&values[1])?;
+
+        let values = fields(&next_line()?, "garbage", 4)?;
+        let garbage_rng_state = parse(&values[0])?;
+        let garbage_lines_cleared = parse(&values[1])?;
+        let garbage_rows_added = parse(&values[2])?;
+        let last_garbage_millis = parse(&values[3])?;
+
+        let values = fields(&next_line()?, "locks", 3)?;
+        let pieces_locked = parse(&values[0])?;
+        let perfect_clears = parse(&values[1])?;
+        let tspin_doubles = parse(&values[2])?;
+
+        let phase_line = next_line()?;
+        let values: Vec<&str> = phase_line.split_whitespace().collect();
+        let phase = match values.as_slice() {
+            ["phase", "falling"] => Phase::Falling,
+            ["phase", "spawning"] => Phase::Spawning,
+            ["phase", "clearing", duration, lines @ ..] => Phase::ClearingLines {
+                lines: lines.iter().map(|y| parse(y)).collect::<io::Result<_>>()?,
+                elapsed_millis: time_millis.saturating_sub(phase_start_millis),
+                duration_millis: parse(duration)?,
+            },
+            _ => return Err(invalid_data("invalid phase")),
+        };
+
+        let values = fields(&next_line()?, "piece", 7)?;
+        let kind = values[0].chars().next().and_then(PieceKind::from_char)
+            .ok_or_else(|| invalid_data("invalid piece kind"))?;
+        let falling_piece = Piece {
+            kind,
+            rotation_index: parse(&values[1])?,
+            origin: parse_tile(&values[2])?,
+            tiles: values[3..].iter().map(|tile| parse_tile(tile)).collect::<io::Result<_>>()?,
+            bounding_box_size: kind.piece_type().bounding_box_size,
+        };
+
+        let values = fields(&next_line()?, "next", 1)?;
+        let next_piece_kind = match values[0].as_str() {
+            "-" => None,
+            kind => Some(kind.chars().next().and_then(PieceKind::from_char)
+                .ok_or_else(|| invalid_data("invalid next piece kind"))?),
+        };
+
+        let row_count: usize = parse(&fields(&next_line()?, "map", 1)?[0])?;
+        let rows = (0..row_count).map(|_| next_line()).collect::<io::Result<Vec<_>>>()?;
+        let board = Board::parse_for(&rows.join("\n"), &settings)?;
+        if board.falling_piece.is_some() {
+            return Err(invalid_data("the map can't have a falling piece"));
+        }
+        let mut map = board.map;
+
+        // Games are only saved while they are being played, so the piece is either falling where it
+        // fits or is the piece that just locked, whose tiles are now set.
+        let rotation_index = falling_piece.rotation_index;
+        let is_piece_shape = rotation_index < 4
+            && board::piece_covering(kind, rotation_index, &falling_piece.tiles, settings.width, settings.buffer_height)
+                .is_some_and(|piece| piece.origin == falling_piece.origin);
+        let is_piece_placed = match phase {
+            Phase::Falling => are_valid_positions(&map, &falling_piece.tiles),
+            _ => falling_piece.tiles.iter().all(|&tile| map.contains(tile)),
+        };
+        if !is_piece_shape || !is_piece_placed {
+            return Err(invalid_data("invalid falling piece"));
+        }
+        if let Phase::ClearingLines { lines, .. } = &phase {
+            if lines.iter().any(|&y| !map.contains(Tile::new(0, y))) {
+                return Err(invalid_data("invalid line to clear"));
+            }
+        }
+
+        let set_line = next_line()?;
+        let set_times: Vec<&str> = match set_line.split_whitespace().collect::<Vec<_>>().as_slice() {
+            ["set", times @ ..] => times.to_vec(),
+            _ => return Err(invalid_data("expected set")),
+        };
+        let set_tiles: Vec<Tile> = set_tiles(&map).map(|map_tile| map_tile.tile).collect();
+        if set_times.len() != set_tiles.len() {
+            return Err(invalid_data("expected a set time for every set tile"));
+        }
+        for (tile, set_millis) in set_tiles.into_iter().zip(set_times) {
+            map[tile].set_millis = parse(set_millis)?;
+        }
+
+        Ok(SavedGame {
+            settings,
+            state: GameState {
+                falling_piece,
+                map,
+                phase,
+                time_millis,
+                last_move_millis,
+                phase_start_millis,
+                lines_cleared,
+                level,
+                score,
+                splits_millis,
+                garbage_lines_cleared,
+                garbage_rng_state,
+                garbage_rows_added,
+                last_garbage_millis,
+                next_piece_kind,
+                pieces_locked,
+                perfect_clears,
+                tspin_doubles,
+            },
+            rng_state,
+        })
+    }
+}
+
+/// Set tiles in the order the map is written, row by row from the top of the buffer.
+fn set_tiles(map: &Map) -> impl Iterator<Item = &MapTile> {
+    (-(map.buffer_height as i16)..map.height as i16)
+        .flat_map(move |y| (0..map.width as i16).map(move |x| &map[Tile::new(x, y)]))
+        .filter(|map_tile| map_tile.is_set)
+}
+
+fn fields(line: &str, name: &str, count: usize) -> io::Result<Vec<String>> {
+    let mut values = line.split_whitespace();
+    if values.next() != Some(name) {
+        return Err(invalid_data(&format!("expected {}", name)));
+    }
+
+    let values: Vec<String> = values.map(String::from).collect();
+    if values.len() != count {
+        return Err(invalid_data(&format!("expected {} values for {}", count, name)));
+    }
+
+    Ok(values)
+}
+
+fn parse<T: std::str::FromStr>(value: &str) -> io::Result<T> {
+    value.parse().map_err(|_| invalid_data(&format!("invalid value {}", value)))
+}
+
+fn format_tile(tile: Tile) -> String {
+    format!("{},{}", tile.x, tile.y)
+}
+
+fn parse_tile(value: &str) -> io::Result<Tile> {
+    let mut coordinates = value.split(',');
+    match (coordinates.next(), coordinates.next(), coordinates.next()) {
+        (Some(x), Some(y), None) => Ok(Tile::new(parse(x)?, parse(y)?)),
+        _ => Err(invalid_data(&format!("invalid tile {}", value))),
+    }
+}
@@ -0,0 +1,40 @@
+use crate::events::TSpin;
+
+pub const LINES_PER_LEVEL: u32 = 10;
+/// Points for each cell a piece is moved down by the player.
+pub const SOFT_DROP_POINTS: u64 = 1;
+
+/// Time it takes a piece to fall one row at each level, starting at level 1, following the
+/// guideline curve `(0.8 - (level - 1) * 0.007) ^ (level - 1)` seconds. Levels past the end of the
+/// table keep the last value.
+pub const GRAVITY_CURVE_MILLIS: [u128; 20] = [
+    1000, 793, 618, 473, 355, 262, 190, 135, 94, 64,
+    43, 28, 18, 11, 7, 4, 3, 2, 1, 1,
+];
+
+pub fn gravity_interval_millis(level: u32) -> u128 {
+    let index = (level.max(1) as usize - 1).min(GRAVITY_CURVE_MILLIS.len() - 1);
+    GRAVITY_CURVE_MILLIS[index]
+}
+
+pub fn level_for_lines(lines_cleared: u32) -> u32 {
+    1 + lines_cleared / LINES_PER_LEVEL
+}
+
+/// Points for a locked piece that cleared `count` lines, before multiplying by the level.
+pub fn line_clear_points(count: usize, tspin: TSpin) -> u64 {
+    match (tspin, count) {
+        (TSpin::None, 0) => 0,
+        (TSpin::None, 1) => 100,
+        (TSpin::None, 2) => 300,
+        (TSpin::None, 3) => 500,
+        (TSpin::None, _) => 800,
+        (TSpin::Mini, 0) => 100,
+        (TSpin::Mini, 1) => 200,
+        (TSpin::Mini, _) => 400,
+        (TSpin::Full, 0) => 400,
+        (TSpin::Full, 1) => 800,
+        (TSpin::Full, 2) => 1200,
+        (TSpin::Full, _) => 1600,
+    }
+}
@@ -0,0 +1,15 @@
+mod input;
+mod drawing;
+mod storage;
+mod playback;
+mod records;
+mod hud;
+mod puzzles;
+
+pub use self::input::{CrosstermInput, wait_for_key};
+pub use self::drawing::{StdoutDrawing, StackVisibility, DEFAULT_FADE_MILLIS};
+pub use self::storage::data_file;
+pub use self::playback::play_replay;
+pub use self::records::PersonalBest;
+pub use self::puzzles::choose_puzzle;
+pub use self::hud::{format_time, game_hud, finesse_hud, result_banner, result_message};
@@ -0,0 +1,203 @@
+use crossterm::terminal::{Clear, ClearType};
+use crossterm::style::{self, style, Color, Colorize};
+use crossterm::{cursor, QueueableCommand, Result};
+use std::io::{stdout, Write, Stdout};
+use crate::game::*;
+use crate::tiles::*;
+use crate::pieces::Piece;
+use crate::drawing::Drawing;
+
+const LINE_CLEAR_FLASH_MILLIS: u128 = 50;
+pub const DEFAULT_FADE_MILLIS: u128 = 5000;
+/// Fading tiles take this long to go from their color to the empty tile color.
+const FADE_OUT_MILLIS: u128 = 1000;
+
+/// How locked tiles are drawn. Only the drawing changes, so the game itself plays the same.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum StackVisibility {
+    #[default]
+    Visible,
+    /// Tiles fade out until they disappear `visible_millis` after being set.
+    Fading { visible_millis: u128 },
+    /// Tiles disappear as soon as they are set.
+    Invisible,
+}
+
+impl StdoutDrawing {
+    
+    fn clear(&mut self) {
+        self.stdout
+            .queue(Clear(ClearType::All))
+            .unwrap();
+    }
+    
+    fn draw_bounds(&mut self, map: &Map) -> Result<()> {
+        self.stdout
+            .queue(cursor::Hide {})?;
+
+        let (width, height) = self.board_size(map);
+        for y in 0..height + 2 {
+            for x in 0..width + 4 {
+                if y == 0 || y == height + 1 || x <= 1 || x >= width + 2 {
+                    self.stdout
+                        .queue(cursor::MoveTo(x, y))?
+                        .queue(style::PrintStyledContent("█".dark_grey()))?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn draw_piece(&mut self, piece: &Piece) -> Result<()> {
+        for tile in piece.tiles.iter().filter(|t| t.y >= 0) {
+            self.draw_tile(*tile, Color::Blue)?;
+        }
+
+        Ok(())
+    }
+
+    fn draw_tile(&mut self, tile: Tile, color: Color) -> Result<()> {
+        let screen_tile = tile.to_screen_space(self.scale as i16);
+        for y in 0..self.scale {
+            self.stdout
+                .queue(cursor::MoveTo(screen_tile.x as u16, screen_tile.y as u16 + y))?
+                .queue(style::PrintStyledContent(style("██".repeat(self.scale as usize)).with(color)))?;
+        }
+
+        Ok(())
+    }
+
+    /// Size of the board on the screen, in characters, without its bounds.
+    fn board_size(&self, map: &Map) -> (u16, u16) {
+        (map.width * 2 * self.scale, map.height * self.scale)
+    }
+
+    fn draw_tiles(&mut self, state: &GameState) -> Result<()> {
+        let is_falling = state.phase == Phase::Falling;
+
+        for y in 0..state.map.height as i16 {
+            let clearing_color = clearing_line_color(&state.phase, y);
+
+            for x in 0..state.map.width as i16 {
+                let tile = Tile::new(x, y);
+                let map_tile = &state.map[tile];
+                let color = match (map_tile.is_set, clearing_color) {
+                    (true, Some(color)) => color,
+                    (true, None) => self.stack_tile_color(map_tile, state.time_millis),
+                    (false, _) => Color::White,
+                };
+
+                if is_falling && state.falling_piece.tiles.contains(&tile) {
+                    continue;
+                }
+
+                self.draw_tile(tile, color)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn stack_tile_color(&self, map_tile: &MapTile, time_millis: u128) -> Color {
+        let (r, g, b) = match map_tile.is_garbage {
+            true => (128, 128, 128),
+            false => (255, 0, 0),
+        };
+
+        let age_millis = time_millis.saturating_sub(map_tile.set_millis);
+        match self.stack_visibility {
+            StackVisibility::Visible if map_tile.is_garbage => Color::DarkGrey,
+            StackVisibility::Visible => Color::Red,
+            StackVisibility::Fading { visible_millis } if age_millis + FADE_OUT_MILLIS > visible_millis => {
+                let fade_start_millis = visible_millis.saturating_sub(FADE_OUT_MILLIS);
+                let progress = ((age_millis - fade_start_millis) as f32 / FADE_OUT_MILLIS as f32).min(1.0);
+                let fade = |value: u8| (value as f32 + (255 - value) as f32 * progress) as u8;
+                Color::Rgb { r: fade(r), g: fade(g), b: fade(b) }
+            }
+            StackVisibility::Fading { .. } => Color::Rgb { r, g, b },
+            StackVisibility::Invisible => Color::White,
+        }
+    }
+
+    /// Draws lines of text to the right of the board, clearing what was there before.
+    pub fn draw_hud(&mut self, map: &Map, lines: &[String]) {
+        let x = self.board_size(map).0 + 6;
+        for (y, line) in lines.iter().enumerate() {
+            self.stdout
+                .queue(cursor::MoveTo(x, y as u16 + 1)).unwrap()
+                .queue(Clear(ClearType::UntilNewLine)).unwrap()
+                .queue(style::Print(line)).unwrap();
+        }
+
+        self.flush();
+    }
+
+    /// Draws a line of text centered over the middle of the board.
+    pub fn draw_banner(&mut self, map: &Map, text: &str) {
+        let (board_width, board_height) = self.board_size(map);
+        let padded = format!("{:^width$}", text, width = board_width as usize);
+        self.stdout
+            .queue(cursor::MoveTo(2, board_height / 2 + 1)).unwrap()
+            .queue(style::PrintStyledContent(style(padded).with(Color::Black).on(Color::Yellow))).unwrap();
+
+        self.flush();
+    }
+
+    fn flush(&mut self) {
+        self.stdout.flush().unwrap();
+    }
+}
+
+/// Cleared lines flash during the first half of the line clear delay and then fade out into the
+/// empty tile color.
+fn clearing_line_color(phase: &Phase, y: i16) -> Option<Color> {
+    match phase {
+        Phase::ClearingLines { lines, elapsed_millis, duration_millis } if lines.contains(&y) => {
+            let progress = *elapsed_millis as f32 / *duration_millis as f32;
+            if progress < 0.5 {
+                match (elapsed_millis / LINE_CLEAR_FLASH_MILLIS) % 2 {
+                    0 => Some(Color::Yellow),
+                    _ => Some(Color::Red),
+                }
+            } else {
+                let fade = (((progress - 0.5) * 2.0).min(1.0) * 255.0) as u8;
+                Some(Color::Rgb { r: 255, g: fade, b: fade })
+            }
+        }
+        _ => None
+    }
+}
+
+pub struct StdoutDrawing {
+    pub stdout: Stdout,
+    pub stack_visibility: StackVisibility,
+    /// Each tile is drawn as this many rows of this many blocks, for big games.
+    pub scale: u16,
+}
+
+impl StdoutDrawing {
+    /// Draws a game with the given settings, with its whole stack visible.
+    pub fn for_settings(settings: &GameSettings) -> StdoutDrawing {
+        StdoutDrawing {
+            stdout: stdout(),
+            stack_visibility: StackVisibility::Visible,
+            scale: if settings.big { BIG_SCALE } else { 1 },
+        }
+    }
+}
+
+impl Drawing for StdoutDrawing {
+    fn init(&mut self) {
+        self.clear();
+    }
+
+    fn draw(&mut self, state: &GameState) {
+        self.draw_bounds(&state.map).unwrap();
+        self.draw_tiles(state).unwrap();
+        if state.phase == Phase::Falling {
+            self.draw_piece(&state.falling_piece).unwrap();
+        }
+        self.flush();
+    }
+}
@@ -0,0 +1,159 @@
+use crate::game::*;
+use crate::modes::*;
+use crate::pieces::PieceKind;
+use crate::grading::master_grade;
+use crate::finesse::FinesseTrainer;
+use super::records::PersonalBest;
+
+/// Formats a game time as minutes, seconds and milliseconds.
+pub fn format_time(millis: u128) -> String {
+    format!("{}:{:02}.{:03}", millis / 60_000, millis / 1000 % 60, millis % 1000)
+}
+
+/// Formats how far a time is ahead (negative) or behind (positive) another one.
+fn format_delta(millis: u128, reference_millis: u128) -> String {
+    match millis < reference_millis {
+        true => format!("-{:.3}", (reference_millis - millis) as f64 / 1000.0),
+        false => format!("+{:.3}", (millis - reference_millis) as f64 / 1000.0),
+    }
+}
+
+/// The lines shown next to the board while playing.
+pub fn game_hud(state: &GameState, settings: &GameSettings, personal_best: Option<&PersonalBest>) -> Vec<String> {
+    match settings.mode {
+        GameMode::Endless => vec![
+            format!("Score  {}", state.score),
+            format!("Level  {}", state.level),
+            format!("Lines  {}", state.lines_cleared),
+        ],
+        GameMode::Zen => vec![
+            "Zen".to_string(),
+            format!("Score  {}", state.score),
+            format!("Lines  {}", state.lines_cleared),
+            format!("Next   {}", state.next_piece_kind.map_or('?', PieceKind::to_char)),
+            String::new(),
+            "U undo, R redo".to_string(),
+            "Shift + I J L O S Z T".to_string(),
+            "chooses the next piece".to_string(),
+        ],
+        GameMode::Finesse => vec![
+            "Finesse".to_string(),
+            format!("Lines  {}", state.lines_cleared),
+            format!("Next   {}", state.next_piece_kind.map_or('?', PieceKind::to_char)),
+            "U undo, R redo".to_string(),
+        ],
+        GameMode::Puzzle { goal, pieces } => vec![
+            "Puzzle".to_string(),
+            format!("Goal   {}", puzzle_goal_text(goal)),
+            format!("Pieces {}/{}", state.pieces_locked, pieces),
+            format!("Lines  {}", state.lines_cleared),
+        ],
+        GameMode::Marathon { lines } => vec![
+            "Marathon".to_string(),
+            format!("Score  {}", state.score),
+            format!("Level  {}/{}", state.level, settings.mode.max_level().unwrap_or(state.level)),
+            format!("Lines  {}/{}", state.lines_cleared.min(lines), lines),
+            format!("Best   {}", personal_best.map_or("-".to_string(), |best| best.score.to_string())),
+        ],
+        GameMode::Sprint { lines } => {
+            let mut hud = vec![
+                format!("Sprint {} lines", lines),
+                format!("Time   {}", format_time(state.time_millis)),
+                format!("Lines  {}/{}", state.lines_cleared.min(lines), lines),
+                format!("Best   {}", personal_best.map_or("-".to_string(), |best| format_time(best.time_millis))),
+                String::new(),
+            ];
+
+            for (index, split_millis) in state.splits_millis.iter().enumerate() {
+                let best_split = personal_best.and_then(|best| best.splits_millis.get(index));
+                hud.push(format!("{:>4}   {}  {}",
+                                 (index as u32 + 1) * SPLIT_LINES,
+                                 format_time(*split_millis),
+                                 best_split.map_or(String::new(), |best| format_delta(*split_millis, *best))));
+            }
+
+            hud
+        }
+        GameMode::Dig { .. } => {
+            let rows = settings.mode.initial_garbage_rows(settings.height);
+            vec![
+                format!("Dig    {} rows", rows),
+                format!("Time   {}", format_time(state.time_millis)),
+                format!("Left   {}", rows.saturating_sub(state.garbage_lines_cleared)),
+                format!("Best   {}", personal_best.map_or("-".to_string(), |best| format_time(best.time_millis))),
+            ]
+        }
+        GameMode::Survival { .. } => vec![
+            "Survival".to_string(),
+            format!("Time   {}", format_time(state.time_millis)),
+            format!("Rows   {}", state.garbage_rows_added),
+            format!("Lines  {}", state.lines_cleared),
+            format!("Best   {}", personal_best.map_or("-".to_string(), |best| format_time(best.time_millis))),
+        ],
+        GameMode::Master => vec![
+            "Master".to_string(),
+            format!("Grade  {}", master_grade(state, false)),
+            format!("Time   {}", format_time(state.time_millis)),
+            format!("Score  {}", state.score),
+            format!("Level  {}/{}", state.level, MASTER_SECTIONS.len()),
+            format!("Lines  {}/{}", state.lines_cleared.min(MASTER_LINES), MASTER_LINES),
+            format!("Best   {}", personal_best.map_or("-".to_string(), |best| best.score.to_string())),
+        ],
+        GameMode::Ultra { duration_millis } => vec![
+            format!("Ultra  {}", format_time(duration_millis)),
+            format!("Left   {}", format_time(duration_millis.saturating_sub(state.time_millis))),
+            format!("Score  {}", state.score),
+            format!("Lines  {}", state.lines_cleared),
+            format!("Best   {}", personal_best.map_or("-".to_string(), |best| best.score.to_string())),
+        ],
+    }
+}
+
+pub fn puzzle_goal_text(goal: PuzzleGoal) -> String {
+    match goal {
+        PuzzleGoal::Lines(1) => "clear 1 line".to_string(),
+        PuzzleGoal::Lines(lines) => format!("clear {} lines", lines),
+        PuzzleGoal::PerfectClear => "perfect clear".to_string(),
+        PuzzleGoal::TSpinDouble => "T-spin double".to_string(),
+    }
+}
+
+/// Fault rate of a finesse game and the fault of the last placement, if it had one.
+pub fn finesse_hud(trainer: &FinesseTrainer) -> Vec<String> {
+    let last_fault = match trainer.last_fault {
+        Some(fault) => format!("FAULT  {} took {} presses, {} needed", fault.kind.to_char(), fault.presses, fault.minimal_presses),
+        None => String::new(),
+    };
+
+    vec![
+        format!("Faults {}/{} ({:.0}%)", trainer.faults, trainer.placements, trainer.fault_rate() * 100.0),
+        last_fault,
+    ]
+}
+
+/// Shown over the board when a game of a mode with a result ends.
+pub fn result_banner(mode: GameMode) -> &'static str {
+    match mode {
+        GameMode::Marathon { .. } => "VICTORY",
+        GameMode::Ultra { .. } => "TIME UP",
+        GameMode::Survival { .. } => "GAME OVER",
+        GameMode::Master => "MASTER",
+        GameMode::Puzzle { .. } => "SOLVED",
+        _ => "FINISH",
+    }
+}
+
+/// Describes the result of a finished game.
+pub fn result_message(state: &GameState, settings: &GameSettings, is_new_best: bool) -> String {
+    let result = match settings.mode {
+        GameMode::Master => format!("grade {} in {}", master_grade(state, true), format_time(state.time_millis)),
+        GameMode::Puzzle { .. } => format!("{} pieces", state.pieces_locked),
+        GameMode::Sprint { .. } | GameMode::Dig { .. } | GameMode::Survival { .. } => format_time(state.time_millis),
+        _ => format!("{} points", state.score),
+    };
+
+    match is_new_best {
+        true => format!("New personal best: {}", result),
+        false => format!("Result: {}", result),
+    }
+}
@@ -0,0 +1,83 @@
+use crossterm::event::{poll, read, Event, KeyCode, KeyEvent};
+use std::time::Duration;
+use crate::input::*;
+use crate::pieces::PieceKind;
+
+pub struct CrosstermInput {}
+
+impl InputSource for CrosstermInput {
+    fn read_input(&mut self) -> Option<InputResult> {
+        match poll(Duration::from_secs(0)) {
+            Ok(has_input) => {
+                match has_input {
+                    true => convert_input(),
+                    false => None
+                }
+            }
+            Err(error) => {
+                println!("Error reading input poll: {}", error);
+                None
+            }
+        }
+    }
+}
+
+/// Blocks until any key is pressed.
+pub fn wait_for_key() {
+    loop {
+        match read() {
+            Ok(Event::Key(_)) | Err(_) => return,
+            _ => continue,
+        }
+    }
+}
+
+fn convert_input() -> Option<InputResult> {
+    match read() {
+        Ok(read_key) => {
+            match read_key {
+                Event::Key(KeyEvent {
+                               code: KeyCode::Left,
+                               ..
+                           }) => Some(InputResult::MoveLeft),
+                Event::Key(KeyEvent {
+                               code: KeyCode::Right,
+                               ..
+                           }) => Some(InputResult::MoveRight),
+                Event::Key(KeyEvent {
+                               code: KeyCode::Up,
+                               ..
+                           }) => Some(InputResult::RotateClockwise),
+                Event::Key(KeyEvent {
+                               code: KeyCode::Char('z'),
+                               ..
+                           }) => Some(InputResult::RotateCounterClockwise),
+                Event::Key(KeyEvent {
+                               code: KeyCode::Down,
+                               ..
+                           }) => Some(InputResult::MoveDown),
+                Event::Key(KeyEvent {
+                               code: KeyCode::Esc,
+                               ..
+                           }) => Some(InputResult::ExitGame),
+                Event::Key(KeyEvent {
+                               code: KeyCode::Char('u'),
+                               ..
+                           }) => Some(InputResult::Undo),
+                Event::Key(KeyEvent {
+                               code: KeyCode::Char('r'),
+                               ..
+                           }) => Some(InputResult::Redo),
+                Event::Key(KeyEvent {
+                               code: KeyCode::Char(c),
+                               ..
+                           }) if c.is_ascii_uppercase() => PieceKind::from_char(c).map(InputResult::ChooseNextPiece),
+                _ => None,
+            }
+        }
+        Err(error) => {
+            println!("Error reading input: {}", error);
+            None
+        }
+    }
+}
@@ -0,0 +1,107 @@
+use crossterm::event::{poll, read, Event, KeyCode, KeyEvent};
+use std::thread;
+use std::time::{Duration, Instant};
+use crate::drawing::Drawing;
+use crate::playback::Playback;
+use super::drawing::StdoutDrawing;
+use super::hud::format_time;
+
+const FRAME_DURATION: Duration = Duration::from_millis(16);
+
+enum PlaybackCommand {
+    TogglePause,
+    SpeedUp,
+    SlowDown,
+    StepFrame,
+    NextPiece,
+    PreviousPiece,
+    TypeDigit(char),
+    EraseDigit,
+    Seek,
+    Quit,
+}
+
+/// Plays a replay on the terminal until it is quit with Esc. Space pauses, up and down change the
+/// speed, `.` steps a frame, `n` and `p` move between pieces, and typing a piece number followed
+/// by Enter seeks to it.
+pub fn play_replay(mut playback: Playback) {
+    let mut drawing = StdoutDrawing::for_settings(&playback.replay().settings);
+    let mut piece_number_input = String::new();
+    let mut last_update = Instant::now();
+    drawing.init();
+
+    loop {
+        while let Some(command) = read_command() {
+            match command {
+                PlaybackCommand::TogglePause => playback.paused = !playback.paused,
+                PlaybackCommand::SpeedUp => playback.speed_up(),
+                PlaybackCommand::SlowDown => playback.slow_down(),
+                PlaybackCommand::StepFrame => {
+                    playback.paused = true;
+                    playback.step_frame();
+                }
+                PlaybackCommand::NextPiece => playback.seek_to_piece(playback.piece_number() + 1),
+                PlaybackCommand::PreviousPiece => playback.seek_to_piece(playback.piece_number().saturating_sub(1).max(1)),
+                PlaybackCommand::TypeDigit(digit) => piece_number_input.push(digit),
+                PlaybackCommand::EraseDigit => {
+                    piece_number_input.pop();
+                }
+                PlaybackCommand::Seek => {
+                    if let Ok(piece_number) = piece_number_input.parse() {
+                        playback.seek_to_piece(piece_number);
+                    }
+                    piece_number_input.clear();
+                }
+                PlaybackCommand::Quit => return,
+            }
+        }
+
+        let now = Instant::now();
+        playback.update(now.duration_since(last_update).as_millis());
+        last_update = now;
+
+        drawing.draw(playback.state());
+        drawing.draw_hud(&playback.state().map, &hud_lines(&playback, &piece_number_input));
+        thread::sleep(FRAME_DURATION);
+    }
+}
+
+fn hud_lines(playback: &Playback, piece_number_input: &str) -> Vec<String> {
+    let status = match (playback.is_finished(), playback.paused) {
+        (true, _) if playback.matches_recording() => "Finished, board matches the recording",
+        (true, _) => "Finished, board DIFFERS from the recording",
+        (false, true) => "Paused",
+        (false, false) => "Playing",
+    };
+
+    vec![
+        format!("Replay {}", status),
+        format!("Time   {} / {}", format_time(playback.time_millis()), format_time(playback.replay().final_time_millis)),
+        format!("Speed  {}x", playback.speed()),
+        format!("Piece  {}", playback.piece_number()),
+        format!("Go to  {}", piece_number_input),
+    ]
+}
+
+fn read_command() -> Option<PlaybackCommand> {
+    if !poll(Duration::from_secs(0)).ok()? {
+        return None;
+    }
+
+    match read().ok()? {
+        Event::Key(KeyEvent { code, .. }) => match code {
+            KeyCode::Char(' ') => Some(PlaybackCommand::TogglePause),
+            KeyCode::Up => Some(PlaybackCommand::SpeedUp),
+            KeyCode::Down => Some(PlaybackCommand::SlowDown),
+            KeyCode::Char('.') => Some(PlaybackCommand::StepFrame),
+            KeyCode::Char('n') => Some(PlaybackCommand::NextPiece),
+            KeyCode::Char('p') => Some(PlaybackCommand::PreviousPiece),
+            KeyCode::Char(digit) if digit.is_ascii_digit() => Some(PlaybackCommand::TypeDigit(digit)),
+            KeyCode::Backspace => Some(PlaybackCommand::EraseDigit),
+            KeyCode::Enter => Some(PlaybackCommand::Seek),
+            KeyCode::Esc => Some(PlaybackCommand::Quit),
+            _ => None,
+        },
+        _ => None,
+    }
+}
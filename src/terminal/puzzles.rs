@@ -0,0 +1,62 @@
+use crossterm::event::{read, Event, KeyCode, KeyEvent};
+use crossterm::terminal::{Clear, ClearType};
+use crossterm::{cursor, style, QueueableCommand, Result};
+use std::io::{self, stdout, Write};
+use std::path::PathBuf;
+use crate::puzzle::Puzzle;
+use super::hud::puzzle_goal_text;
+
+/// Lists the puzzles of a pack with a preview of the selected one, until one is chosen with Enter
+/// or the browser is left with Esc. Up and down move the selection, and puzzles marked as
+/// `solved` are checked.
+pub fn choose_puzzle(pack: &[(PathBuf, io::Result<Puzzle>)], solved: &[bool], mut selected: usize) -> Option<usize> {
+    loop {
+        draw_browser(pack, solved, selected).ok()?;
+        if let Event::Key(KeyEvent { code, .. }) = read().ok()? {
+            match code {
+                KeyCode::Up => selected = selected.saturating_sub(1),
+                KeyCode::Down => selected = (selected + 1).min(pack.len().saturating_sub(1)),
+                KeyCode::Enter if matches!(pack.get(selected), Some((_, Ok(_)))) => return Some(selected),
+                KeyCode::Esc => return None,
+                _ => {}
+            }
+        }
+    }
+}
+
+fn draw_browser(pack: &[(PathBuf, io::Result<Puzzle>)], solved: &[bool], selected: usize) -> Result<()> {
+    let mut stdout = stdout();
+    stdout
+        .queue(Clear(ClearType::All))?
+        .queue(cursor::MoveTo(0, 0))?
+        .queue(style::Print("Puzzles (Up/Down to select, Enter to play, Esc to quit)"))?;
+
+    for (index, (path, puzzle)) in pack.iter().enumerate() {
+        let marker = if index == selected { '>' } else { ' ' };
+        let check = if solved.get(index).copied().unwrap_or(false) { '*' } else { ' ' };
+        let line = match puzzle {
+            Ok(puzzle) => format!("{} {} {} - {}, {} pieces", marker, check, puzzle.name, puzzle_goal_text(puzzle.goal), puzzle.queue.len()),
+            Err(error) => format!("{}   {}: {}", marker, path.display(), error),
+        };
+        stdout
+            .queue(cursor::MoveTo(0, index as u16 + 2))?
+            .queue(style::Print(line))?;
+    }
+
+    if let Some((_, Ok(puzzle))) = pack.get(selected) {
+        let top = pack.len() as u16 + 3;
+        let queue: String = puzzle.queue.iter().map(|kind| kind.to_char()).collect();
+        stdout
+            .queue(cursor::MoveTo(0, top))?
+            .queue(style::Print(format!("Queue {}", queue)))?;
+        for (y, row) in puzzle.board.to_string().lines().enumerate() {
+            let row: String = row.chars().map(|c| if c == '.' { "  " } else { "██" }).collect();
+            stdout
+                .queue(cursor::MoveTo(0, top + 1 + y as u16))?
+                .queue(style::Print(format!("|{}|", row)))?;
+        }
+    }
+
+    stdout.flush()?;
+    Ok(())
+}
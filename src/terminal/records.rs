@@ -0,0 +1,75 @@
+use std::fs;
+use std::io;
+use crate::game::{GameState, GameSettings};
+use crate::modes::GameMode;
+use super::storage::data_file;
+
+/// The best result of a game mode, kept in the data directory.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct PersonalBest {
+    pub time_millis: u128,
+    pub score: u64,
+    pub splits_millis: Vec<u128>,
+}
+
+impl PersonalBest {
+    pub fn from_state(state: &GameState) -> PersonalBest {
+        PersonalBest {
+            time_millis: state.time_millis,
+            score: state.score,
+            splits_millis: state.splits_millis.clone(),
+        }
+    }
+
+    /// Loads the personal best for the mode of `settings`, kept apart for big games. Modes without
+    /// personal bests, and modes that were never completed, have none.
+    pub fn load(settings: &GameSettings) -> Option<PersonalBest> {
+        let contents = fs::read_to_string(data_file(&file_name(settings)?)).ok()?;
+        let mut best = PersonalBest::default();
+        for line in contents.lines() {
+            let mut values = line.split_whitespace();
+            match values.next()? {
+                "time" => best.time_millis = values.next()?.parse().ok()?,
+                "score" => best.score = values.next()?.parse().ok()?,
+                "splits" => best.splits_millis = values.map(|split| split.parse().ok()).collect::<Option<_>>()?,
+                _ => {}
+            }
+        }
+
+        Some(best)
+    }
+
+    pub fn save(&self, settings: &GameSettings) -> io::Result<()> {
+        let file_name = match file_name(settings) {
+            Some(file_name) => file_name,
+            None => return Ok(()),
+        };
+
+        let splits: Vec<String> = self.splits_millis.iter().map(|split| format!(" {}", split)).collect();
+        fs::write(data_file(&file_name),
+                  format!("time {}\nscore {}\nsplits{}\n", self.time_millis, self.score, splits.concat()))
+    }
+
+    /// Whether a finished game did better than this personal best, in the terms of its mode.
+    pub fn is_beaten_by(&self, mode: GameMode, state: &GameState) -> bool {
+        match mode {
+            GameMode::Sprint { .. } | GameMode::Dig { .. } => state.time_millis < self.time_millis,
+            GameMode::Survival { .. } => state.time_millis > self.time_millis,
+            _ => state.score > self.score,
+        }
+    }
+}
+
+fn file_name(settings: &GameSettings) -> Option<String> {
+    let name = match settings.mode {
+        GameMode::Endless | GameMode::Zen | GameMode::Finesse | GameMode::Puzzle { .. } => return None,
+        GameMode::Marathon { lines } => format!("marathon-{}", lines),
+        GameMode::Sprint { lines } => format!("sprint-{}", lines),
+        GameMode::Ultra { duration_millis } => format!("ultra-{}", duration_millis),
+        GameMode::Dig { rows } => format!("dig-{}", rows),
+        GameMode::Survival { interval_millis } => format!("survival-{}", interval_millis),
+        GameMode::Master => "master".to_string(),
+    };
+    let size = if settings.big { "-big" } else { "" };
+    Some(format!("{}{}.best", name, size))
+}
@@ -0,0 +1,17 @@
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+const DATA_DIRECTORY: &str = ".tetris-rs";
+
+/// Path of a file in the directory where the terminal game keeps its data, `~/.tetris-rs`. Falls
+/// back to the current directory if there is no home directory.
+pub fn data_file(name: &str) -> PathBuf {
+    let directory = match env::var_os("HOME").or_else(|| env::var_os("USERPROFILE")) {
+        Some(home) => PathBuf::from(home).join(DATA_DIRECTORY),
+        None => PathBuf::from("."),
+    };
+
+    let _ = fs::create_dir_all(&directory);
+    directory.join(name)
+}
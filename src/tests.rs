@@ -1,10 +1,18 @@
+// The original tests predate these lints and are kept as they were written.
+#![allow(clippy::assertions_on_constants, clippy::init_numbered_fields)]
+
 use super::*;
+#[cfg(feature = "terminal")]
+use crate::terminal::StdoutDrawing;
 use std::time::Instant;
-use crate::time::{ManualClock, ManualClockInstant};
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::collections::HashSet;
 
 #[test]
 fn rotate_clockwise_plank_piece() {
     let mut piece = Piece {
+        kind: PieceKind::I,
         tiles: vec![Tile::new(5, 5), Tile::new(6, 5), Tile::new(7, 5), Tile::new(8, 5)],
         bounding_box_size: 4,
         origin: Tile::new(5, 4),
@@ -22,6 +30,7 @@ fn rotate_clockwise_plank_piece() {
 #[test]
 fn rotate_counterclockwise_plank_piece() {
     let mut piece = Piece {
+        kind: PieceKind::I,
         tiles: vec![Tile::new(5, 5), Tile::new(6, 5), Tile::new(7, 5), Tile::new(8, 5)],
         bounding_box_size: 4,
         origin: Tile::new(5, 4),
@@ -39,6 +48,7 @@ fn rotate_counterclockwise_plank_piece() {
 #[test]
 fn rotate_clockwise_l_piece() {
     let mut piece = Piece {
+        kind: PieceKind::L,
         tiles: vec![Tile::new(5, 5), Tile::new(6, 5), Tile::new(7, 5), Tile::new(7, 6)],
         bounding_box_size: 3,
         origin: Tile::new(5, 4),
@@ -56,6 +66,7 @@ fn rotate_clockwise_l_piece() {
 #[test]
 fn rotate_counterclockwise_l_piece() {
     let mut piece = Piece {
+        kind: PieceKind::L,
         tiles: vec![Tile::new(5, 5), Tile::new(6, 5), Tile::new(7, 5), Tile::new(7, 6)],
         bounding_box_size: 3,
         origin: Tile::new(5, 4),
@@ -70,30 +81,47 @@ fn rotate_counterclockwise_l_piece() {
     assert_eq!(piece.tiles[3], Tile::new(7, 4));
 }
 
-fn create_test_game<F>(next_input_func: F) -> Game<
-    ManualInput<F>, 
-    ManualPieceTypeSelector, 
-    ManualClockInstant, 
-    ManualClock, 
-    NoopDrawing> where F: FnMut() -> Option<InputResult> {
-   Game::new(
-        ManualInput { next_input_func },
-        ManualPieceTypeSelector { piece_index: 0 },
-        ManualClock { now_milliseconds: 0 },
-        NoopDrawing {})
+fn create_test_game<F>(next_input_func: F) -> Game<ManualInput<F>, ManualPieceTypeSelector, ManualClock>
+    where F: FnMut() -> Option<InputResult> {
+    create_test_game_with_settings(next_input_func, test_settings())
+}
+
+fn test_settings() -> GameSettings {
+    GameSettings {
+        line_clear_delay_millis: 0,
+        spawn_delay_millis: 0,
+        ..GameSettings::default()
+    }
+}
+
+fn create_test_game_with_settings<F>(next_input_func: F, settings: GameSettings) -> Game<ManualInput<F>, ManualPieceTypeSelector, ManualClock>
+    where F: FnMut() -> Option<InputResult> {
+    GameBuilder::new()
+        .input(ManualInput { next_input_func })
+        .piece_type_selector(ManualPieceTypeSelector { piece_index: 0 })
+        .clock(ManualClock { now_milliseconds: 0 })
+        .settings(settings)
+        .build().unwrap()
+}
+
+fn create_queued_test_game(piece_index: usize, settings: GameSettings) -> Game<QueuedInput, ManualPieceTypeSelector, ManualClock> {
+    GameBuilder::new()
+        .piece_type_selector(ManualPieceTypeSelector { piece_index })
+        .clock(ManualClock { now_milliseconds: 0 })
+        .settings(settings)
+        .build().unwrap()
 }
 
-fn _create_test_visual_game<F>(next_input_func: F) -> Game<
-    ManualInput<F>, 
-    ManualPieceTypeSelector, 
-    ManualClockInstant, 
-    ManualClock, 
-    StdoutDrawing>  where F: FnMut() -> Option<InputResult> {
-    Game::new(
-        ManualInput { next_input_func },
-        ManualPieceTypeSelector { piece_index: 0 },
-        ManualClock { now_milliseconds: 0 },
-        StdoutDrawing{ stdout: stdout() })
+#[cfg(feature = "terminal")]
+fn _create_test_visual_game<F>(next_input_func: F) -> Game<ManualInput<F>, ManualPieceTypeSelector, ManualClock, StdoutDrawing>
+    where F: FnMut() -> Option<InputResult> {
+    GameBuilder::new()
+        .input(ManualInput { next_input_func })
+        .piece_type_selector(ManualPieceTypeSelector { piece_index: 0 })
+        .clock(ManualClock { now_milliseconds: 0 })
+        .drawing(StdoutDrawing::for_settings(&GameSettings::default()))
+        .settings(test_settings())
+        .build().unwrap()
 }
 
 #[test]
@@ -105,7 +133,7 @@ fn lost_game() {
     loop {
         match game.run_frame() {
             FrameResult::GameInProgress => {},
-            FrameResult::PlayerLost => break,
+            FrameResult::PlayerLost(_) => break,
             _ => assert!(false)
         }
         
@@ -136,79 +164,1256 @@ fn multiple_inputs_on_single_frame() {
     game.run_frame();
     
     let mut falling_piece_tiles = game.state.falling_piece.tiles.iter();
-    assert!(falling_piece_tiles.any(|&tile| tile == Tile { x: 0, y: 1 }));
-    assert!(falling_piece_tiles.any(|&tile| tile == Tile { x: 1, y: 1 }));
-    assert!(falling_piece_tiles.any(|&tile| tile == Tile { x: 2, y: 1 }));
-    assert!(falling_piece_tiles.any(|&tile| tile == Tile { x: 3, y: 1 }));
+    assert!(falling_piece_tiles.any(|&tile| tile == Tile { x: 0, y: -1 }));
+    assert!(falling_piece_tiles.any(|&tile| tile == Tile { x: 1, y: -1 }));
+    assert!(falling_piece_tiles.any(|&tile| tile == Tile { x: 2, y: -1 }));
+    assert!(falling_piece_tiles.any(|&tile| tile == Tile { x: 3, y: -1 }));
 }
 
 #[test]
 fn clear_single_line() {
     let mut input_queue = InputList { 0: Vec::new() };
     input_queue.push_many(InputResult::MoveLeft, 3);
-    input_queue.push_many(InputResult::MoveDown, 19);
+    input_queue.push_many(InputResult::MoveDown, 21);
     input_queue.push_many(InputResult::MoveRight, 3);
-    input_queue.push_many(InputResult::MoveDown, 19);
+    input_queue.push_many(InputResult::MoveDown, 21);
     input_queue.push(InputResult::RotateClockwise);
-    input_queue.push(InputResult::MoveLeft);
-    input_queue.push_many(InputResult::MoveDown, 17);
+    input_queue.push(InputResult::MoveRight);
+    input_queue.push_many(InputResult::MoveDown, 20);
     input_queue.push(InputResult::RotateClockwise);
-    input_queue.push_many(InputResult::MoveLeft, 2);
-    input_queue.push_many(InputResult::MoveDown, 17);
+    input_queue.push_many(InputResult::MoveDown, 20);
 
     let mut game = create_test_game(|| input_queue.pop_front());
     
     game.run_frame();
     
-    assert_line(&game.state, 19, [false, false, false, false, true, true, false, false, false, false]);
+    assert_line(&game.state, 19, &[false, false, false, false, true, true, false, false, false, false]);
 }
 
 #[test]
 fn clear_multiple_lines() {
     let mut input_queue = InputList { 0: Vec::new() };
     input_queue.push(InputResult::RotateClockwise);
-    input_queue.push_many(InputResult::MoveLeft, 6);
-    input_queue.push_many(InputResult::MoveDown, 17);
-    input_queue.push(InputResult::RotateClockwise);
-    input_queue.push_many(InputResult::MoveLeft, 5);
-    input_queue.push_many(InputResult::MoveDown, 17);
-    input_queue.push(InputResult::RotateClockwise);
     input_queue.push_many(InputResult::MoveLeft, 4);
-    input_queue.push_many(InputResult::MoveDown, 17);
+    input_queue.push_many(InputResult::MoveDown, 20);
     input_queue.push(InputResult::RotateClockwise);
     input_queue.push_many(InputResult::MoveLeft, 3);
-    input_queue.push_many(InputResult::MoveDown, 17);
+    input_queue.push_many(InputResult::MoveDown, 20);
     input_queue.push(InputResult::RotateClockwise);
     input_queue.push_many(InputResult::MoveLeft, 2);
-    input_queue.push_many(InputResult::MoveDown, 17);
+    input_queue.push_many(InputResult::MoveDown, 20);
     input_queue.push(InputResult::RotateClockwise);
     input_queue.push_many(InputResult::MoveLeft, 1);
-    input_queue.push_many(InputResult::MoveDown, 17);
+    input_queue.push_many(InputResult::MoveDown, 20);
     input_queue.push(InputResult::RotateClockwise);
-    input_queue.push_many(InputResult::MoveDown, 17);
+    input_queue.push_many(InputResult::MoveDown, 20);
     input_queue.push(InputResult::RotateClockwise);
     input_queue.push_many(InputResult::MoveRight, 1);
-    input_queue.push_many(InputResult::MoveDown, 17);
+    input_queue.push_many(InputResult::MoveDown, 20);
     input_queue.push(InputResult::RotateClockwise);
     input_queue.push_many(InputResult::MoveRight, 2);
-    input_queue.push_many(InputResult::MoveDown, 17);
+    input_queue.push_many(InputResult::MoveDown, 20);
     input_queue.push(InputResult::RotateClockwise);
     input_queue.push_many(InputResult::MoveRight, 3);
-    input_queue.push_many(InputResult::MoveDown, 17);
+    input_queue.push_many(InputResult::MoveDown, 20);
+    input_queue.push(InputResult::RotateClockwise);
+    input_queue.push_many(InputResult::MoveRight, 4);
+    input_queue.push_many(InputResult::MoveDown, 20);
+    input_queue.push(InputResult::RotateClockwise);
+    input_queue.push_many(InputResult::MoveRight, 5);
+    input_queue.push_many(InputResult::MoveDown, 20);
 
     let mut game = create_test_game(|| input_queue.pop_front());
     
     game.run_frame();
 
-    assert_line(&game.state, 16, [false, false, false, false, false, false, false, false, false, false]);
-    assert_line(&game.state, 17, [false, false, false, false, false, false, false, false, false, false]);
-    assert_line(&game.state, 18, [false, false, false, false, false, false, false, false, false, false]);
-    assert_line(&game.state, 19, [false, false, false, false, false, false, false, false, false, false]);
+    assert_line(&game.state, 16, &[false, false, false, false, false, false, false, false, false, false]);
+    assert_line(&game.state, 17, &[false, false, false, false, false, false, false, false, false, false]);
+    assert_line(&game.state, 18, &[false, false, false, false, false, false, false, false, false, false]);
+    assert_line(&game.state, 19, &[false, false, false, false, false, false, false, false, false, false]);
+}
+
+#[test]
+fn custom_board_dimensions() {
+    let game = create_test_game_with_settings(|| None, GameSettings { width: 4, height: 30, ..test_settings() });
+
+    assert_eq!(game.state.map.tiles.len(), 4);
+    assert!(game.state.map.tiles.iter().all(|column| column.len() == 30 + DEFAULT_BUFFER_HEIGHT as usize));
+    assert_eq!(game.state.map[Tile::new(3, 29)].tile, Tile::new(3, 29));
+    assert_eq!(game.state.falling_piece.tiles, vec![Tile::new(0, -1), Tile::new(1, -1), Tile::new(2, -1), Tile::new(3, -1)]);
+}
+
+#[test]
+fn clear_line_on_narrow_board() {
+    let mut input_queue = InputList(Vec::new());
+    input_queue.push_many(InputResult::MoveDown, 7);
+
+    let mut game = create_test_game_with_settings(|| input_queue.pop_front(), GameSettings { width: 4, height: 6, ..test_settings() });
+
+    game.run_frame();
+
+    assert_line(&game.state, 5, &[false, false, false, false]);
+    assert_eq!(game.state.falling_piece.tiles[0], Tile::new(0, -1));
+}
+
+#[test]
+fn piece_falls_to_bottom_of_tall_board() {
+    let mut input_queue = InputList(Vec::new());
+    input_queue.push_many(InputResult::MoveDown, 41);
+
+    let mut game = create_test_game_with_settings(|| input_queue.pop_front(), GameSettings { width: 20, height: 40, ..test_settings() });
+
+    game.run_frame();
+
+    let mut expected_line = [false; 20];
+    expected_line[8..12].copy_from_slice(&[true; 4]);
+    assert_line(&game.state, 39, &expected_line);
+}
+
+#[test]
+fn rotate_inside_buffer_zone() {
+    let mut game = create_test_game(|| None);
+
+    game.try_rotate_clockwise();
+
+    assert_eq!(game.state.falling_piece.tiles, vec![Tile::new(4, -3), Tile::new(4, -2), Tile::new(4, -1), Tile::new(4, 0)]);
+}
+
+#[test]
+fn stack_above_visible_playfield() {
+    let mut game = create_test_game(|| None);
+    for y in 1..DEFAULT_HEIGHT as i16 {
+        game.state.map[Tile::new(0, y)].is_set = true;
+    }
+
+    game.try_rotate_clockwise();
+    for _ in 0..4 {
+        game.move_left();
+    }
+    game.fall_piece();
+
+    assert_eq!(game.top_out, None);
+    for y in -3..=0 {
+        assert!(game.state.map[Tile::new(0, y)].is_set);
+    }
+}
+
+#[test]
+fn spawn_at_top_without_buffer() {
+    let game = create_test_game_with_settings(|| None, GameSettings { buffer_height: 0, ..test_settings() });
+
+    assert_eq!(game.state.falling_piece.tiles[0], Tile::new(3, 1));
+}
+
+#[test]
+fn block_out_when_spawning_over_stack() {
+    let mut game = create_test_game(|| None);
+    fill_columns(&mut game.state.map, 3..7, 1..DEFAULT_HEIGHT as i16);
+
+    game.fall_piece();
+    for x in 3..7 {
+        game.state.map[Tile::new(x, -1)].is_set = true;
+    }
+    game.fall_piece();
+
+    assert_eq!(game.top_out, Some(TopOut::BlockOut));
+    assert_eq!(game.run_frame(), FrameResult::PlayerLost(TopOut::BlockOut));
+}
+
+#[test]
+fn lock_out_when_locking_above_visible_playfield() {
+    let mut game = create_test_game(|| None);
+    fill_columns(&mut game.state.map, 3..7, 0..DEFAULT_HEIGHT as i16);
+
+    game.fall_piece();
+
+    assert_eq!(game.top_out, Some(TopOut::LockOut));
+    assert_eq!(game.run_frame(), FrameResult::PlayerLost(TopOut::LockOut));
+}
+
+#[test]
+fn partial_lock_out_when_enabled() {
+    let mut game = create_test_game_with_settings(|| None, GameSettings { partial_lock_out: true, ..test_settings() });
+    fill_columns(&mut game.state.map, 4..5, 1..DEFAULT_HEIGHT as i16);
+
+    game.try_rotate_clockwise();
+    game.fall_piece();
+
+    assert_eq!(game.top_out, Some(TopOut::PartialLockOut));
+}
+
+#[test]
+fn line_clear_delay_collapses_lines_after_elapsed() {
+    let mut game = create_test_game_with_settings(|| None, GameSettings { line_clear_delay_millis: 300, ..test_settings() });
+    fill_columns(&mut game.state.map, 0..3, 19..20);
+    fill_columns(&mut game.state.map, 7..10, 19..20);
+
+    for _ in 0..21 {
+        game.fall_piece();
+    }
+
+    assert_eq!(game.state.phase, Phase::ClearingLines { lines: vec![19], elapsed_millis: 0, duration_millis: 300 });
+
+    game.clock.now_milliseconds = 299;
+    game.run_frame();
+
+    assert_eq!(game.state.phase, Phase::ClearingLines { lines: vec![19], elapsed_millis: 299, duration_millis: 300 });
+    assert_line(&game.state, 19, &[true; 10]);
+
+    game.clock.now_milliseconds = 300;
+    game.run_frame();
+
+    assert_eq!(game.state.phase, Phase::Falling);
+    assert_line(&game.state, 19, &[false; 10]);
+    assert_eq!(game.state.falling_piece.tiles[0], Tile::new(3, -1));
+}
+
+#[test]
+fn spawn_delay_ignores_input_until_next_piece() {
+    let mut input_queue = InputList(Vec::new());
+    input_queue.push_many(InputResult::MoveLeft, 3);
+
+    let mut game = create_test_game_with_settings(|| input_queue.pop_front(), GameSettings { spawn_delay_millis: 100, ..test_settings() });
+    for _ in 0..21 {
+        game.fall_piece();
+    }
+
+    assert_eq!(game.state.phase, Phase::Spawning);
+
+    game.clock.now_milliseconds = 99;
+    game.run_frame();
+
+    assert_eq!(game.state.phase, Phase::Spawning);
+
+    game.clock.now_milliseconds = 100;
+    game.run_frame();
+
+    assert_eq!(game.state.phase, Phase::Falling);
+    assert_eq!(game.state.falling_piece.tiles[0], Tile::new(3, -1));
+    assert_line(&game.state, 19, &[false, false, false, true, true, true, true, false, false, false]);
+}
+
+#[test]
+fn emit_events_for_moves_lock_and_spawn() {
+    let mut input_queue = InputList(Vec::new());
+    input_queue.push(InputResult::MoveLeft);
+    input_queue.push_many(InputResult::MoveDown, 21);
+
+    let mut game = create_test_game(|| input_queue.pop_front());
+
+    assert_eq!(game.events, vec![GameEvent::PieceSpawned { kind: PieceKind::I }]);
+
+    game.run_frame();
+
+    let mut expected_events = vec![GameEvent::PieceMoved { delta: Tile::new(-1, 0) }];
+    expected_events.extend(vec![GameEvent::PieceMoved { delta: Tile::new(0, 1) }; 20]);
+    expected_events.push(GameEvent::Locked {
+        kind: PieceKind::I,
+        tiles: vec![Tile::new(2, 19), Tile::new(3, 19), Tile::new(4, 19), Tile::new(5, 19)],
+    });
+    expected_events.push(GameEvent::PieceSpawned { kind: PieceKind::I });
+    assert_eq!(game.events, expected_events);
+}
+
+#[test]
+fn notify_subscribed_listeners() {
+    let received_events = Rc::new(RefCell::new(Vec::new()));
+    let listener_events = received_events.clone();
+
+    let mut game = create_test_game(|| Some(InputResult::ExitGame));
+    game.subscribe(Box::new(move |event: &GameEvent| listener_events.borrow_mut().push(event.clone())));
+    game.try_rotate_clockwise();
+    game.run_frame();
+
+    assert_eq!(*received_events.borrow(), vec![GameEvent::Rotated { clockwise: true, kick: None }]);
+    assert!(game.events.is_empty());
+}
+
+#[test]
+fn emit_lines_cleared_with_tspin_double() {
+    let mut input_queue = InputList(Vec::new());
+    input_queue.push(InputResult::RotateClockwise);
+    input_queue.push_many(InputResult::MoveDown, 19);
+    input_queue.push(InputResult::RotateClockwise);
+    input_queue.push(InputResult::MoveDown);
+
+    let mut game = create_test_game(|| input_queue.pop_front());
+    fill_columns(&mut game.state.map, 0..4, 17..18);
+    fill_columns(&mut game.state.map, 0..3, 18..19);
+    fill_columns(&mut game.state.map, 6..10, 18..19);
+    fill_columns(&mut game.state.map, 0..4, 19..20);
+    fill_columns(&mut game.state.map, 5..10, 19..20);
+    game.piece_type_selector.piece_index = 6;
+    game.spawn_piece();
+
+    game.run_frame();
+
+    assert!(game.events.contains(&GameEvent::LinesCleared { count: 2, tspin: TSpin::Full }));
+    assert_line(&game.state, 19, &[true, true, true, true, false, false, false, false, false, false]);
+}
+
+#[test]
+fn simulation_steps_actions_and_time() {
+    let mut simulation = Simulation::new(test_settings(), 7).unwrap();
+
+    let events = simulation.step(&[InputResult::MoveLeft], 0);
+    assert_eq!(events, vec![GameEvent::PieceMoved { delta: Tile::new(-1, 0) }]);
+
+    assert!(simulation.step(&[], 999).is_empty());
+
+    let events = simulation.step(&[], 1);
+    assert_eq!(events, vec![GameEvent::PieceMoved { delta: Tile::new(0, 1) }]);
+    assert_eq!(simulation.time_millis(), 1000);
+}
+
+#[test]
+fn simulations_with_same_seed_are_deterministic() {
+    let drop_all = [InputResult::MoveDown; 25];
+    let mut first = Simulation::new(test_settings(), 42).unwrap();
+    let mut second = Simulation::new(test_settings(), 42).unwrap();
+
+    for _ in 0..10 {
+        assert_eq!(first.step(&drop_all, 16), second.step(&drop_all, 16));
+    }
+
+    assert_eq!(first.state().falling_piece, second.state().falling_piece);
+}
+
+#[test]
+fn cloned_simulation_is_independent() {
+    let mut simulation = Simulation::new(test_settings(), 3).unwrap();
+    let mut clone = simulation.clone();
+
+    clone.step(&[InputResult::MoveRight], 0);
+
+    assert_eq!(clone.state().falling_piece.origin, simulation.state().falling_piece.origin + Tile::new(1, 0));
+    simulation.step(&[InputResult::MoveRight], 0);
+    assert_eq!(clone.state().falling_piece, simulation.state().falling_piece);
+}
+
+#[test]
+fn build_game_with_defaults() {
+    let mut game = GameBuilder::new()
+        .clock(ManualClock { now_milliseconds: 0 })
+        .width(4)
+        .height(8)
+        .build().unwrap();
+
+    assert_eq!(game.state.map.width, 4);
+    assert_eq!(game.state.map.height, 8);
+    assert_eq!(game.settings.buffer_height, DEFAULT_BUFFER_HEIGHT);
+    assert_eq!(game.run_frame(), FrameResult::GameInProgress);
+}
+
+#[test]
+fn reject_settings_without_room_for_pieces() {
+    assert!(GameBuilder::new().width(3).build().is_err());
+    assert!(GameBuilder::new().settings(GameSettings { height: 1, buffer_height: 0, ..test_settings() }).build().is_err());
+    assert!(Simulation::new(GameSettings { width: 3, ..test_settings() }, 0).is_err());
+
+    let mut game = GameBuilder::new()
+        .clock(ManualClock { now_milliseconds: 0 })
+        .settings(GameSettings { width: MIN_WIDTH, height: 2, buffer_height: 0, ..test_settings() })
+        .build().unwrap();
+    for input in [InputResult::MoveLeft, InputResult::MoveRight, InputResult::RotateClockwise].iter().cycle().take(60) {
+        game.input.inputs.extend(vec![*input; 4]);
+        game.clock.now_milliseconds += 1000;
+        game.run_frame();
+    }
+    assert!(game.is_over());
+}
+
+#[test]
+fn save_and_resume_game_in_progress() {
+    let mut input_queue = InputList(Vec::new());
+    input_queue.push_many(InputResult::MoveDown, 30);
+    input_queue.push(InputResult::RotateClockwise);
+    input_queue.push(InputResult::MoveLeft);
+
+    let mut game = GameBuilder::new()
+        .input(ManualInput { next_input_func: || input_queue.pop_front() })
+        .piece_type_selector(SeededPieceTypeSelector::new(11))
+        .clock(ManualClock { now_milliseconds: 0 })
+        .settings(GameSettings { line_clear_delay_millis: 0, ..GameSettings::default() })
+        .build().unwrap();
+    game.clock.now_milliseconds = 1500;
+    game.run_frame();
+    game.state.map[Tile::new(0, 19)].is_set = true;
+
+    let mut file = Vec::new();
+    game.save().write(&mut file).unwrap();
+    let saved_game = SavedGame::read(&file[..]).unwrap();
+
+    let mut resumed_game = GameBuilder::new()
+        .piece_type_selector(SeededPieceTypeSelector::new(0))
+        .clock(ManualClock { now_milliseconds: 90_000 })
+        .build().unwrap();
+    resumed_game.resume(saved_game).unwrap();
+
+    assert_eq!(resumed_game.state, game.state);
+    assert_eq!(resumed_game.settings.spawn_delay_millis, DEFAULT_SPAWN_DELAY_MILLIS);
+    assert_eq!(resumed_game.now_millis(), 1500);
+
+    game.clock.now_milliseconds = 1700;
+    resumed_game.clock.now_milliseconds = 90_200;
+    game.run_frame();
+    resumed_game.run_frame();
+
+    assert_eq!(resumed_game.state, game.state);
+    assert_eq!(resumed_game.state.phase, Phase::Falling);
+    assert_eq!(resumed_game.piece_type_selector.rng_state, game.piece_type_selector.rng_state);
+}
+
+#[test]
+fn reject_unsupported_save_version() {
+    let file = "tetris-save 999\n";
+
+    let error = SavedGame::read(file.as_bytes()).err().unwrap();
+
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+}
+
+#[test]
+fn reject_save_with_invalid_falling_piece() {
+    let mut game = GameBuilder::new()
+        .piece_type_selector(SeededPieceTypeSelector::new(11))
+        .clock(ManualClock { now_milliseconds: 0 })
+        .build().unwrap();
+    game.run_frame();
+    let save = |game: &Game<_, _, _>| {
+        let mut file = Vec::new();
+        game.save().write(&mut file).unwrap();
+        String::from_utf8(file).unwrap()
+    };
+    let file = save(&game);
+    let piece_line = file.lines().find(|line| line.starts_with("piece ")).unwrap();
+    let phase_line = file.lines().find(|line| line.starts_with("phase ")).unwrap();
+    let piece_tile = game.state.falling_piece.tiles[0];
+    game.state.map[piece_tile].is_set = true;
+
+    let invalid_files = vec![
+        save(&game),
+        file.replace(piece_line, "piece L 0 3,-2 500,500 501,500 502,500 501,499"),
+        file.replace(piece_line, "piece J 7 3,-2 3,-2 3,-1 4,-1 5,-1"),
+        file.replace(piece_line, "piece J 0 2,-2 3,-2 3,-1 4,-1 5,-1"),
+        file.replace(phase_line, "phase clearing 0 20"),
+    ];
+
+    assert!(SavedGame::read(file.as_bytes()).is_ok());
+    for invalid_file in &invalid_files {
+        let error = SavedGame::read(invalid_file.as_bytes()).err().unwrap();
+
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    }
+}
+
+#[test]
+fn sprint_ends_when_line_goal_is_reached() {
+    let mut game = create_queued_test_game(0, GameSettings { width: 4, height: 6, mode: GameMode::Sprint { lines: 12 }, ..test_settings() });
+
+    let mut result = FrameResult::GameInProgress;
+    while result == FrameResult::GameInProgress {
+        game.clock.now_milliseconds += 100;
+        game.input.inputs.extend(vec![InputResult::MoveDown; 8]);
+        result = game.run_frame();
+    }
+
+    assert_eq!(result, FrameResult::GoalReached);
+    assert_eq!(game.events.last(), Some(&GameEvent::GoalReached));
+    assert_eq!(game.state.lines_cleared, 12);
+    assert_eq!(game.state.splits_millis, vec![900]);
+    assert_eq!(game.state.time_millis, 1100);
+
+    game.clock.now_milliseconds += 5000;
+    assert_eq!(game.run_frame(), FrameResult::GoalReached);
+    assert_eq!(game.state.time_millis, 1100);
+}
+
+#[test]
+fn ultra_ends_when_time_runs_out() {
+    let mut game = create_test_game_with_settings(|| None, GameSettings { mode: GameMode::Ultra { duration_millis: 5000 }, ..test_settings() });
+    let spawn_origin = game.state.falling_piece.origin;
+    game.state.level = 5;
+
+    game.clock.now_milliseconds = 7000;
+
+    assert_eq!(game.run_frame(), FrameResult::GoalReached);
+    assert_eq!(game.events.last(), Some(&GameEvent::GoalReached));
+    assert_eq!(game.state.time_millis, 5000);
+    assert_eq!(game.state.falling_piece.origin, spawn_origin + Tile::new(0, 4));
+}
+
+#[test]
+fn gravity_follows_level_curve() {
+    let mut game = create_test_game(|| None);
+    assert_eq!(game.gravity_interval_millis(), 1000);
+
+    game.state.level = 2;
+    assert_eq!(game.gravity_interval_millis(), 793);
+
+    game.state.level = 50;
+    assert_eq!(game.gravity_interval_millis(), 1);
+}
+
+#[test]
+fn score_line_clears_and_level_up() {
+    let events = Rc::new(RefCell::new(Vec::new()));
+    let mut game = create_queued_test_game(0, GameSettings { width: 4, height: 6, ..test_settings() });
+    let listener_events = events.clone();
+    game.subscribe(Box::new(move |event: &GameEvent| listener_events.borrow_mut().push(event.clone())));
+
+    while game.state.lines_cleared < 10 {
+        game.clock.now_milliseconds += 100;
+        game.input.inputs.push_back(InputResult::MoveDown);
+        game.run_frame();
+    }
+
+    let events = events.borrow();
+    let soft_drops = events.iter().filter(|event| **event == GameEvent::PieceMoved { delta: Tile::new(0, 1) }).count();
+    assert_eq!(game.state.lines_cleared, 10);
+    assert_eq!(game.state.score, 10 * 100 + soft_drops as u64);
+    assert_eq!(game.state.level, 2);
+    assert_eq!(events.iter().filter(|event| **event == GameEvent::LevelUp { level: 2 }).count(), 1);
+}
+
+#[test]
+fn marathon_ends_on_last_level() {
+    let mut game = create_queued_test_game(0, GameSettings { width: 4, height: 6, mode: GameMode::Marathon { lines: 20 }, ..test_settings() });
+
+    let mut result = FrameResult::GameInProgress;
+    while result == FrameResult::GameInProgress {
+        game.clock.now_milliseconds += 100;
+        game.input.inputs.push_back(InputResult::MoveDown);
+        result = game.run_frame();
+    }
+
+    assert_eq!(result, FrameResult::GoalReached);
+    assert_eq!(game.state.lines_cleared, 20);
+    assert_eq!(game.state.level, 2);
+    assert_eq!(GameMode::Marathon { lines: DEFAULT_MARATHON_LINES }.max_level(), Some(15));
+}
+
+#[test]
+fn dig_counts_only_garbage_lines() {
+    let settings = GameSettings { width: 4, height: 8, mode: GameMode::Dig { rows: 2 }, garbage_seed: 17, ..test_settings() };
+    let mut game = create_test_game_with_settings(|| None, settings);
+    let drop_piece = |game: &mut Game<_, _, _>| {
+        game.events.clear();
+        while !game.events.iter().any(|event| matches!(event, GameEvent::Locked { .. })) {
+            game.fall_piece();
+        }
+    };
+
+    for y in 0..6 {
+        assert!(!game.state.map.is_garbage_row(y));
+    }
+    for y in 6..8 {
+        let set_tiles: Vec<&MapTile> = (0..4).map(|x| &game.state.map[Tile::new(x, y)]).filter(|tile| tile.is_set).collect();
+        assert_eq!(set_tiles.len(), 3);
+        assert!(set_tiles.iter().all(|tile| tile.is_garbage));
+    }
+
+    for x in 0..4 {
+        game.state.map[Tile::new(x, 7)].is_set = true;
+    }
+    drop_piece(&mut game);
+
+    assert_eq!(game.state.lines_cleared, 2);
+    assert_eq!(game.state.garbage_lines_cleared, 1);
+    assert!(!game.goal_reached);
+    assert!(game.state.map.is_garbage_row(7));
+
+    for x in 0..4 {
+        game.state.map[Tile::new(x, 7)].is_set = true;
+    }
+    drop_piece(&mut game);
+
+    assert_eq!(game.state.garbage_lines_cleared, 2);
+    assert!(game.goal_reached);
+}
+
+#[test]
+fn survival_garbage_rises_on_accelerating_timer() {
+    let settings = GameSettings { mode: GameMode::Survival { interval_millis: 4000 }, ..test_settings() };
+    let mut game = create_test_game_with_settings(|| None, settings);
+
+    game.clock.now_milliseconds = 7599;
+    game.run_frame();
+    assert_eq!(game.state.garbage_rows_added, 1);
+
+    game.clock.now_milliseconds = 10840;
+    game.run_frame();
+
+    assert_eq!(game.state.garbage_rows_added, 3);
+    assert_eq!(game.state.last_garbage_millis, 10840);
+    for y in 17..20 {
+        assert!(game.state.map.is_garbage_row(y));
+    }
+    assert!(!game.state.map.is_garbage_row(16));
+}
+
+#[test]
+fn garbage_row_has_one_hole() {
+    let mut rng_state = 7;
+
+    let row = garbage_row(DEFAULT_WIDTH, &mut rng_state);
+
+    assert_eq!(row.len(), DEFAULT_WIDTH as usize);
+    assert_eq!(row.iter().filter(|&&is_filled| !is_filled).count(), 1);
+    assert_eq!(garbage_row(0, &mut rng_state), Vec::<bool>::new());
+}
+
+#[test]
+fn rising_garbage_pushes_falling_piece_up() {
+    let mut game = create_test_game(|| None);
+    while game.state.falling_piece.tiles[0].y < 19 {
+        game.fall_piece();
+    }
+    game.events.clear();
+
+    game.insert_garbage_rows(&[vec![false, true, true, true, true, true, true, true, true, true]]);
+
+    assert!(game.state.falling_piece.tiles.iter().all(|tile| tile.y == 18));
+    assert_eq!(game.events, vec![
+        GameEvent::GarbageAdded { rows: 1 },
+        GameEvent::PieceMoved { delta: Tile::new(0, -1) },
+    ]);
+    assert!(!game.state.map[Tile::new(0, 18)].is_set);
+    assert!(game.state.map[Tile::new(1, 19)].is_garbage);
+}
+
+#[test]
+fn rising_garbage_tops_out_when_stack_leaves_map() {
+    let mut game = create_test_game(|| None);
+    game.state.map[Tile::new(0, -(DEFAULT_BUFFER_HEIGHT as i16))].is_set = true;
+
+    game.insert_garbage_rows(&[vec![true; DEFAULT_WIDTH as usize]]);
+
+    assert_eq!(game.top_out, Some(TopOut::GarbageOut));
+}
+
+#[test]
+fn inserting_more_rows_than_map_holds_pushes_everything_out() {
+    let mut map = Map::new(4, 2, 0);
+    let rows = vec![vec![true, false, true, true], vec![false, true, true, true], vec![true, true, false, true]];
+
+    assert!(map.insert_rows_at_bottom(&rows, 0));
+
+    assert!(!map[Tile::new(0, 0)].is_set && map[Tile::new(1, 0)].is_set);
+    assert!(map[Tile::new(0, 1)].is_set && !map[Tile::new(2, 1)].is_set);
+}
+
+#[test]
+fn master_20g_drops_piece_to_floor_and_holds_it_for_lock_delay() {
+    let settings = GameSettings { mode: GameMode::Master, ..test_settings() };
+    let mut game = create_test_game_with_settings(|| None, settings);
+    game.state.level = 3;
+
+    game.clock.now_milliseconds = 1;
+    game.run_frame();
+    assert!(game.state.falling_piece.tiles.iter().all(|tile| tile.y == 19));
+    assert_eq!(game.state.phase, Phase::Falling);
+
+    game.clock.now_milliseconds = 499;
+    game.run_frame();
+    assert_eq!(game.state.phase, Phase::Falling);
+
+    game.clock.now_milliseconds = 500;
+    game.run_frame();
+    assert_ne!(game.state.phase, Phase::Falling);
+    assert!(game.state.map[Tile::new(3, 19)].is_set);
+}
+
+#[test]
+fn master_sections_replace_settings_delays() {
+    let settings = GameSettings { mode: GameMode::Master, ..test_settings() };
+    let mut game = create_test_game_with_settings(|| None, settings);
+    assert_eq!(game.gravity_interval_millis(), 64);
+    assert_eq!(game.spawn_delay_millis(), 400);
+
+    game.state.level = 10;
+    assert_eq!(game.gravity_interval_millis(), 0);
+    assert_eq!(game.lock_delay_millis(), Some(250));
+    assert_eq!(game.line_clear_delay_millis(), 50);
+}
+
+#[test]
+fn master_grade_follows_score_and_time() {
+    let mut game = create_test_game(|| None);
+    assert_eq!(master_grade(&game.state, false), "9");
+
+    game.state.score = 16_000;
+    assert_eq!(master_grade(&game.state, false), "S1");
+
+    game.state.score = 150_000;
+    game.state.time_millis = GRAND_MASTER_TIME_MILLIS;
+    assert_eq!(master_grade(&game.state, false), "S9");
+    assert_eq!(master_grade(&game.state, true), "GM");
+
+    game.state.time_millis += 1;
+    assert_eq!(master_grade(&game.state, true), "S9");
+}
+
+#[test]
+fn map_tiles_remember_when_they_were_set() {
+    let mut game = create_test_game(|| None);
+    game.state.time_millis = 300;
+    while game.state.falling_piece.tiles[0].y < 19 {
+        game.fall_piece();
+    }
+    game.fall_piece();
+    assert!(game.state.map[Tile::new(3, 19)].is_set);
+    assert_eq!(game.state.map[Tile::new(3, 19)].set_millis, 300);
+
+    game.state.time_millis = 700;
+    game.insert_garbage_rows(&[vec![false, true, true, true, true, true, true, true, true, true]]);
+
+    assert_eq!(game.state.map[Tile::new(3, 18)].set_millis, 300);
+    assert_eq!(game.state.map[Tile::new(1, 19)].set_millis, 700);
+}
+
+#[test]
+fn big_game_counts_each_row_of_blocks_as_two_lines() {
+    let mut game = create_queued_test_game(0, GameSettings { width: 4, height: 6, big: true, ..test_settings() });
+
+    while game.state.lines_cleared == 0 {
+        game.clock.now_milliseconds += 1000;
+        game.run_frame();
+    }
+
+    assert_eq!(game.state.lines_cleared, 2);
+    assert_eq!(game.state.score, 300);
+}
+
+#[test]
+fn zen_has_no_gravity_and_undoes_placements() {
+    let mut game = create_queued_test_game(0, GameSettings { mode: GameMode::Zen, ..test_settings() });
+    let spawned_piece = game.state.falling_piece.clone();
+
+    game.clock.now_milliseconds = 60_000;
+    game.run_frame();
+    assert_eq!(game.state.falling_piece, spawned_piece);
+
+    game.input.inputs.push_back(InputResult::ChooseNextPiece(PieceKind::T));
+    while game.undo_history.is_empty() {
+        game.input.inputs.push_back(InputResult::MoveDown);
+        game.run_frame();
+    }
+    assert!(game.state.map[Tile::new(3, 19)].is_set);
+    assert_eq!(game.state.falling_piece.kind, PieceKind::T);
+    let placed_state = game.state.clone();
+
+    game.input.inputs.push_back(InputResult::Undo);
+    game.run_frame();
+    assert!(!game.state.map[Tile::new(3, 19)].is_set);
+    assert_eq!(game.state.falling_piece, spawned_piece);
+    assert_eq!(game.state.next_piece_kind, Some(PieceKind::T));
+
+    game.input.inputs.push_back(InputResult::Redo);
+    game.run_frame();
+    assert_eq!(game.state.map, placed_state.map);
+    assert_eq!(game.state.falling_piece, placed_state.falling_piece);
+    assert!(game.redo_history.is_empty());
+}
+
+#[test]
+fn zen_clears_stack_instead_of_topping_out() {
+    let settings = GameSettings { mode: GameMode::Zen, ..test_settings() };
+    let mut game = create_test_game_with_settings(|| None, settings);
+    fill_columns(&mut game.state.map, 0..10, -2..20);
+    game.events.clear();
+
+    game.spawn_piece();
+
+    assert!(!game.is_over());
+    assert!(!game.state.map[Tile::new(0, 19)].is_set);
+    assert_eq!(game.events, vec![GameEvent::PieceSpawned { kind: PieceKind::I }, GameEvent::StackCleared]);
+}
+
+#[test]
+fn finesse_table_counts_fewest_presses_from_spawn() {
+    let table = FinesseTable::new(DEFAULT_WIDTH, DEFAULT_BUFFER_HEIGHT).unwrap();
+    let o_piece = spawn_piece_of_type(PieceKind::O.piece_type(), DEFAULT_WIDTH, DEFAULT_BUFFER_HEIGHT);
+    assert_eq!(table.minimal_presses(&o_piece), Some(0));
+
+    let mut left_o_piece = o_piece.clone();
+    left_o_piece.tiles.iter_mut().for_each(|tile| *tile += Tile::new(-4, 10));
+    assert_eq!(table.minimal_presses(&left_o_piece), Some(4));
+
+    let mut vertical_i_piece = spawn_piece_of_type(PieceKind::I.piece_type(), DEFAULT_WIDTH, DEFAULT_BUFFER_HEIGHT);
+    rotate_clockwise(&mut vertical_i_piece);
+    assert_eq!(table.minimal_presses(&vertical_i_piece), Some(1));
+}
+
+#[test]
+fn finesse_mode_flags_placements_with_extra_presses() {
+    let mut game = create_queued_test_game(0, GameSettings { mode: GameMode::Finesse, ..test_settings() });
+
+    game.input.inputs.extend(vec![InputResult::MoveLeft, InputResult::MoveRight]);
+    while game.finesse.as_ref().unwrap().placements == 0 {
+        game.input.inputs.push_back(InputResult::MoveDown);
+        game.run_frame();
+    }
+
+    let fault = FinesseFault { kind: PieceKind::I, presses: 2, minimal_presses: 0 };
+    assert!(game.events.contains(&GameEvent::FinesseFault(fault)));
+    let trainer = game.finesse.as_ref().unwrap();
+    assert_eq!(trainer.last_fault, Some(fault));
+    assert_eq!(trainer.fault_rate(), 1.0);
+}
+
+fn create_puzzle_game(text: &str) -> Game<QueuedInput, SequencePieceTypeSelector, ManualClock> {
+    let puzzle = Puzzle::read(text.as_bytes()).unwrap();
+    let mut game = GameBuilder::new()
+        .piece_type_selector(puzzle.piece_type_selector())
+        .clock(ManualClock { now_milliseconds: 0 })
+        .settings(GameSettings { line_clear_delay_millis: 0, spawn_delay_millis: 0, ..puzzle.settings() })
+        .build().unwrap();
+    game.state.map = puzzle.map();
+    game
+}
+
+fn drop_pieces_until_over<PTS: PieceTypeSelector>(game: &mut Game<QueuedInput, PTS, ManualClock>) -> FrameResult {
+    loop {
+        game.input.inputs.push_back(InputResult::MoveDown);
+        match game.run_frame() {
+            FrameResult::GameInProgress => continue,
+            result => return result,
+        }
+    }
+}
+
+#[test]
+fn read_puzzle_file() {
+    let text = "# A comment\nname Flat\ngoal lines 2\nqueue I O\nboard\n###....###\nGGG.GGGGGG\n";
+    let puzzle = Puzzle::read(text.as_bytes()).unwrap();
+
+    assert_eq!(puzzle.name, "Flat");
+    assert_eq!(puzzle.goal, PuzzleGoal::Lines(2));
+    assert_eq!(puzzle.queue, vec![PieceKind::I, PieceKind::O]);
+    assert_eq!(puzzle.settings().mode, GameMode::Puzzle { goal: PuzzleGoal::Lines(2), pieces: 2 });
+    let map = puzzle.map();
+    assert!(map[Tile::new(0, 18)].is_set && !map[Tile::new(0, 18)].is_garbage);
+    assert!(!map[Tile::new(3, 18)].is_set);
+    assert!(map[Tile::new(0, 19)].is_garbage);
+    assert!(!map[Tile::new(3, 19)].is_set);
+
+    assert!(Puzzle::read("name Bad\ngoal lines 1\nqueue I\nboard\n###\n####\n".as_bytes()).is_err());
+    assert!(Puzzle::read("name Bad\ngoal lines 1\nqueue X\nboard\n####\n".as_bytes()).is_err());
+    assert!(Puzzle::read("name Bad\ngoal lines 1\nqueue I\nboard\n#.#\n".as_bytes()).is_err());
+}
+
+#[test]
+fn shipped_puzzles_match_puzzles_directory() {
+    let directory = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("puzzles");
+    let loaded = |pack: Vec<(std::path::PathBuf, std::io::Result<Puzzle>)>| pack.into_iter()
+        .map(|(path, puzzle)| (path.file_name().unwrap().to_owned(), puzzle.unwrap()))
+        .collect::<Vec<_>>();
+
+    assert_eq!(loaded(shipped_puzzle_pack()), loaded(load_puzzle_pack(&directory).unwrap()));
+}
+
+#[test]
+fn puzzle_is_solved_by_reaching_its_goal() {
+    let mut game = create_puzzle_game("name PC\ngoal perfect-clear\nqueue I\nboard\n###....###\n");
+
+    assert_eq!(drop_pieces_until_over(&mut game), FrameResult::GoalReached);
+    assert_eq!(game.state.perfect_clears, 1);
+    assert_eq!(game.state.pieces_locked, 1);
+}
+
+#[test]
+fn puzzle_fails_when_pieces_run_out() {
+    let mut game = create_puzzle_game("name Two\ngoal lines 2\nqueue I\nboard\n###....###\n#########.\n");
+
+    assert_eq!(drop_pieces_until_over(&mut game), FrameResult::PlayerLost(TopOut::OutOfPieces));
+    assert_eq!(game.state.lines_cleared, 1);
+}
+
+#[test]
+fn sequence_selector_deals_pieces_in_order_and_starts_over() {
+    let mut selector = SequencePieceTypeSelector::new(vec![PieceKind::T, PieceKind::S]);
+    let kinds: Vec<PieceKind> = (0..3).map(|_| selector.select_piece_type(&ALL_PIECES).kind).collect();
+
+    assert_eq!(kinds, vec![PieceKind::T, PieceKind::S, PieceKind::T]);
+    assert_eq!(selector.remaining(), &[PieceKind::S]);
+}
+
+#[test]
+fn board_round_trips_through_text() {
+    let text = "\
+....t.....
+...ttt....
+ZZ.#..IIII
+GGGGG.GGGG
+";
+    let board = Board::parse(text, 10, 20, 20).unwrap();
+
+    assert_eq!(board.to_string(), text);
+    assert_eq!(board.map[Tile::new(0, 18)].kind, Some(PieceKind::Z));
+    assert!(board.map[Tile::new(3, 18)].is_set);
+    assert_eq!(board.map[Tile::new(3, 18)].kind, None);
+    assert!(board.map.is_garbage_row(19));
+    let piece = board.falling_piece.unwrap();
+    assert_eq!(piece.kind, PieceKind::T);
+    assert_eq!(piece.rotation_index, 0);
+}
+
+#[test]
+fn board_finds_rotation_of_falling_piece() {
+    let board = Board::parse("
+        .l..
+        .l..
+        .ll.
+    ", 4, 20, 20).unwrap();
+
+    let piece = board.falling_piece.unwrap();
+    assert_eq!(piece.rotation_index, 1);
+    let mut spawned = spawn_piece_of_type(PieceKind::L.piece_type(), 4, 20);
+    rotate_clockwise(&mut spawned);
+    let delta = piece.origin - spawned.origin;
+    assert!(spawned.tiles.iter().all(|tile| piece.tiles.contains(&(*tile + delta))));
+}
+
+#[test]
+fn reject_invalid_boards() {
+    assert!(Board::parse("....", 10, 20, 20).is_err());
+    assert!(Board::parse("..x.", 4, 20, 20).is_err());
+    assert!(Board::parse("tttt", 4, 20, 20).is_err());
+    assert!(Board::parse("tt..\nss..", 4, 20, 20).is_err());
+    assert!(Board::parse(&"....\n".repeat(3), 4, 1, 1).is_err());
+}
+
+#[test]
+fn clear_lines_on_board_set_up_from_text() {
+    let mut input_queue = InputList(Vec::new());
+    input_queue.push_many(InputResult::MoveDown, 5);
+    let mut game = create_test_game(|| input_queue.pop_front());
+    let board = Board::parse_for("
+        .........i
+        .........i
+        .........i
+        .........i
+        IIIIOOJJL.
+        GGGGGGGGG.
+        TTT#SSZZZ.
+        ##.######.
+    ", &game.settings).unwrap();
+    game.state.map = board.map;
+    game.state.falling_piece = board.falling_piece.unwrap();
+
+    game.run_frame();
+
+    let board = Board { map: game.state.map.clone(), falling_piece: None };
+    assert_eq!(board.to_string(), "##.######I\n");
+    assert_eq!(game.state.lines_cleared, 3);
+}
+
+#[test]
+fn decode_empty_fumen() {
+    let pages = decode_fumen("https://harddrop.com/fumen/?v115@vhAAgH", 20, 20).unwrap();
+
+    assert_eq!(pages.len(), 1);
+    assert_eq!(pages[0].board.to_string(), "");
+    assert_eq!(pages[0].board.falling_piece, None);
+    assert!(pages[0].lock);
+    assert_eq!(encode_fumen(&pages).unwrap(), "v115@vhAAgH");
+}
+
+#[test]
+fn fumen_round_trips_through_known_strings() {
+    let known_fumens = [
+        ("v115@vhAVQJ", vec!["....t.....\n...ttt....\n"]),
+        ("v115@vhAVQYCAouBAA", vec!["....t.....\n...ttt....\n"]),
+        ("v115@bhI8KepIJvhAAAA", vec![
+            ".........i\n.........i\n.........i\nGGGGGGGGGi\n",
+            ".........I\n.........I\n.........I\n",
+        ]),
+        ("v115@vhCAgHAAAAAA", vec!["", "", ""]),
+    ];
+
+    for (fumen, boards) in &known_fumens {
+        let pages = decode_fumen(fumen, 20, 20).unwrap();
+        let decoded_boards: Vec<String> = pages.iter().map(|page| page.board.to_string()).collect();
+
+        assert_eq!(&decoded_boards, boards);
+        assert_eq!(&encode_fumen(&pages).unwrap(), fumen);
+    }
+}
+
+#[test]
+fn decode_fumen_pieces_and_comments() {
+    let pages = decode_fumen("v115@vhAVQYCAouBAA", 20, 20).unwrap();
+    assert_eq!(pages[0].comment, "hi");
+    let piece = pages[0].board.falling_piece.as_ref().unwrap();
+    assert_eq!(piece.kind, PieceKind::T);
+    assert_eq!(piece.rotation_index, 0);
+
+    let pages = decode_fumen("v115@bhI8KepIJvhAAAA", 20, 20).unwrap();
+    let piece = pages[0].board.falling_piece.as_ref().unwrap();
+    assert_eq!(piece.kind, PieceKind::I);
+    assert_eq!(piece.rotation_index, 1);
+    assert_eq!(pages[1].board.map[Tile::new(9, 19)].kind, Some(PieceKind::I));
+}
+
+#[test]
+fn encode_game_state_as_fumen() {
+    let mut input_queue = InputList(Vec::new());
+    input_queue.push_many(InputResult::MoveDown, 22);
+    input_queue.push(InputResult::RotateClockwise);
+    let mut game = create_test_game(|| input_queue.pop_front());
+    game.run_frame();
+
+    let page = FumenPage::from_state(&game.state);
+    let fumen = encode_fumen(std::slice::from_ref(&page)).unwrap();
+
+    assert_eq!(decode_fumen(&fumen, 20, 20).unwrap(), vec![FumenPage {
+        board: Board::parse(&page.board.to_string(), 10, 20, 20).unwrap(),
+        ..page
+    }]);
+}
+
+#[test]
+fn export_replay_as_fumen() {
+    let replay = record_test_replay(8, 1000);
+    let fumen = replay_to_fumen(&replay).unwrap();
+    let pages = decode_fumen(&fumen, replay.settings.height, replay.settings.buffer_height).unwrap();
+
+    let mut playback = Playback::new(replay.clone()).unwrap();
+    let mut locks = 0;
+    while !playback.is_finished() {
+        locks += playback.next_frame().iter().filter(|event| matches!(event, GameEvent::Locked { .. })).count();
+    }
+    assert!(locks > 0);
+    assert_eq!(pages.len(), locks + 1);
+    assert_eq!(pages.last().unwrap().board.to_string(), Board::from_state(playback.state()).to_string());
+}
+
+#[test]
+fn reject_invalid_fumens() {
+    assert!(decode_fumen("v110@7eAA4G", 20, 20).is_err());
+    assert!(decode_fumen("v115@vh", 20, 20).is_err());
+    assert!(decode_fumen("v115@vhAAg!", 20, 20).is_err());
+    assert!(decode_fumen("v115@vws97w", 20, 20).is_err());
+    let wide_page = FumenPage { board: Board::parse("", 12, 20, 20).unwrap(), comment: String::new(), lock: true };
+    assert!(encode_fumen(&[wide_page]).is_err());
+}
+
+#[test]
+fn reachable_placements_on_empty_map() {
+    for (piece_index, expected_positions) in [(0, 17), (3, 9), (6, 34)] {
+        let game = create_queued_test_game(piece_index, test_settings());
+        let placements = reachable_placements(&game.state);
+        let positions: HashSet<(usize, Vec<Tile>)> = placements.iter()
+            .map(|placement| {
+                let mut tiles = placement.piece.tiles.clone();
+                tiles.sort_by_key(|tile| (tile.y, tile.x));
+                (if piece_index == 6 { placement.piece.rotation_index } else { 0 }, tiles)
+            })
+            .collect();
+
+        assert_eq!(positions.len(), expected_positions);
+        assert!(placements.iter().all(|placement| placement.piece.tiles.iter().any(|tile| tile.y == 19)));
+    }
+}
+
+#[test]
+fn no_placements_once_piece_locked() {
+    let mut game = create_queued_test_game(0, GameSettings::default());
+    while game.state.pieces_locked == 0 {
+        game.fall_piece();
+    }
+
+    assert_eq!(game.state.phase, Phase::Spawning);
+    assert!(reachable_placements(&game.state).is_empty());
+}
+
+#[test]
+fn reachable_placements_include_spins_and_their_inputs() {
+    let mut game = create_queued_test_game(6, test_settings());
+    game.state.map = Board::parse_for("
+        ####......
+        ###...####
+        ####.#####
+    ", &game.settings).unwrap().map;
+    let placements = reachable_placements(&game.state);
+
+    for placement in &placements {
+        let mut moved_game = game.clone();
+        moved_game.input.inputs.extend(placement.inputs.iter().copied());
+        moved_game.run_frame();
+        assert_eq!(moved_game.state.falling_piece, placement.piece);
+    }
+
+    let slot = [Tile::new(3, 18), Tile::new(4, 18), Tile::new(5, 18), Tile::new(4, 19)];
+    let spin = placements.iter()
+        .find(|placement| slot.iter().all(|tile| placement.piece.tiles.contains(tile)))
+        .unwrap();
+    assert!(spin.last_rotation_kick.is_some());
+
+    game.input.inputs.extend(spin.inputs.iter().copied());
+    game.input.inputs.push_back(InputResult::MoveDown);
+    game.run_frame();
+    assert!(game.events.contains(&GameEvent::LinesCleared { count: 2, tspin: TSpin::Full }));
+}
+
+#[test]
+fn replay_round_trips_through_file() {
+    let replay = Replay {
+        seed: 0xDEAD_BEEF,
+        settings: GameSettings { width: 6, partial_lock_out: true, mode: GameMode::Sprint { lines: 40 }, ..GameSettings::default() },
+        inputs: vec![
+            RecordedInput { time_millis: 0, input: InputResult::MoveLeft },
+            RecordedInput { time_millis: 0, input: InputResult::RotateCounterClockwise },
+            RecordedInput { time_millis: 70_000, input: InputResult::MoveDown },
+        ],
+        final_time_millis: 71_234,
+        board_checksum: 99,
+    };
+
+    let mut file = Vec::new();
+    replay.write(&mut file).unwrap();
+
+    assert_eq!(Replay::read(&mut &file[..]).unwrap(), replay);
+    assert!(Replay::read(&mut &file[..file.len() - 1]).is_err());
+}
+
+#[test]
+fn reject_invalid_replays() {
+    let replay = Replay {
+        seed: 0,
+        settings: GameSettings::default(),
+        inputs: Vec::new(),
+        final_time_millis: 0,
+        board_checksum: 0,
+    };
+    let mut narrow_map_file = Vec::new();
+    Replay { settings: GameSettings { width: 3, ..GameSettings::default() }, ..replay.clone() }
+        .write(&mut narrow_map_file).unwrap();
+    let mut late_input_file = Vec::new();
+    replay.write(&mut late_input_file).unwrap();
+    late_input_file.pop();
+    late_input_file.push(17);
+    for _ in 0..17 {
+        // A varint holding the longest possible delay before a MoveDown.
+        late_input_file.push(0xF2);
+        late_input_file.extend(vec![0xFF; 17]);
+        late_input_file.push(0x03);
+    }
+
+    for file in &[narrow_map_file, late_input_file] {
+        let error = Replay::read(&mut &file[..]).err().unwrap();
+
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    }
+}
+
+fn record_test_replay(seed: u64, frames: usize) -> Replay {
+    let mut game = GameBuilder::new()
+        .piece_type_selector(SeededPieceTypeSelector::new(seed))
+        .clock(ManualClock { now_milliseconds: 0 })
+        .settings(GameSettings { spawn_delay_millis: 30, ..GameSettings::default() })
+        .build().unwrap();
+    game.start_recording();
+
+    let moves = [InputResult::MoveRight, InputResult::MoveDown, InputResult::RotateCounterClockwise, InputResult::MoveLeft];
+    for frame in 0..frames {
+        game.clock.now_milliseconds += 17;
+        if frame % 5 == 0 {
+            game.input.inputs.push_back(moves[frame % moves.len()]);
+        }
+        if game.run_frame() != FrameResult::GameInProgress {
+            break;
+        }
+    }
+
+    game.finish_recording().unwrap()
+}
+
+#[test]
+fn recorded_inputs_reproduce_game_at_any_frame_rate() {
+    let replay = record_test_replay(5, 3000);
+    assert!(replay.inputs.len() > 100);
+
+    let mut played_back = GameBuilder::new()
+        .piece_type_selector(SeededPieceTypeSelector::new(replay.seed))
+        .clock(ManualClock { now_milliseconds: 0 })
+        .settings(replay.settings)
+        .build().unwrap();
+    for recorded_input in &replay.inputs {
+        played_back.clock.now_milliseconds = recorded_input.time_millis;
+        played_back.input.inputs.push_back(recorded_input.input);
+        played_back.run_frame();
+    }
+    played_back.clock.now_milliseconds = replay.final_time_millis;
+    played_back.run_frame();
+    let mut playback = Playback::new(replay.clone()).unwrap();
+    playback.finish();
+
+    assert_eq!(board_checksum(&played_back.state.map), replay.board_checksum);
+    assert_eq!(&played_back.state, playback.state());
+}
+
+#[test]
+fn play_back_replay_at_every_speed() {
+    let replay = record_test_replay(8, 4000);
+    assert!(replay.verify());
+
+    for _ in 0..PLAYBACK_SPEEDS.len() {
+        let mut playback = Playback::new(replay.clone()).unwrap();
+        while !playback.is_finished() {
+            playback.update(33);
+        }
+        assert!(playback.matches_recording());
+        playback.slow_down();
+    }
+
+    let mut playback = Playback::new(replay.clone()).unwrap();
+    for _ in 0..PLAYBACK_SPEEDS.len() {
+        playback.speed_up();
+    }
+    assert_eq!(playback.speed(), 8.0);
+    playback.paused = true;
+    playback.update(1000);
+    assert_eq!(playback.time_millis(), 0);
+    playback.step_frame();
+    assert_eq!(playback.time_millis(), FRAME_MILLIS);
+}
+
+#[test]
+fn seek_replay_to_piece() {
+    let replay = record_test_replay(21, 4000);
+
+    let mut playback = Playback::new(replay.clone()).unwrap();
+    playback.seek_to_piece(6);
+    assert_eq!(playback.piece_number(), 6);
+    let state_at_sixth_piece = playback.state().clone();
+
+    playback.seek_to_piece(9);
+    assert_eq!(playback.piece_number(), 9);
+    playback.seek_to_piece(6);
+    assert_eq!(playback.state(), &state_at_sixth_piece);
+
+    playback.finish();
+    assert!(playback.matches_recording());
+
+    let mut tampered = replay;
+    tampered.inputs.retain(|input| input.input != InputResult::MoveLeft);
+    assert!(!tampered.verify());
+}
+
+fn fill_columns(map: &mut Map, columns: std::ops::Range<i16>, rows: std::ops::Range<i16>) {
+    for x in columns {
+        for y in rows.clone() {
+            map[Tile::new(x, y)].is_set = true;
+        }
+    }
 }
 
-fn assert_line(state: &GameState, index: usize, is_set_values: [bool; WIDTH as usize]) {
-    for i in 0..WIDTH as usize {
-        assert_eq!(state.map.tiles[i][index].is_set, is_set_values[i]);
+fn assert_line(state: &GameState, index: usize, is_set_values: &[bool]) {
+    assert_eq!(state.map.width as usize, is_set_values.len());
+    for (i, is_set) in is_set_values.iter().enumerate() {
+        assert_eq!(state.map[Tile::new(i as i16, index as i16)].is_set, *is_set);
     }
 }
 
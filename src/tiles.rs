@@ -1,6 +1,6 @@
 use std::ops::{Add, AddAssign, Sub, SubAssign};
 
-#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+#[derive(Clone, Copy, Eq, PartialEq, Hash, Debug)]
 pub struct Tile {
     pub x: i16,
     pub y: i16,
@@ -11,10 +11,12 @@ impl Tile {
         Tile { x, y }
     }
 
-    pub fn to_screen_space(self) -> Tile {
+    /// Top left corner of the tile on the screen, when each tile is drawn as `scale` by `scale`
+    /// cells.
+    pub fn to_screen_space(self, scale: i16) -> Tile {
         Tile {
-            x: self.x * 2 + 2,
-            y: self.y + 1,
+            x: self.x * 2 * scale + 2,
+            y: self.y * scale + 1,
         }
     }
 }
@@ -1,13 +1,17 @@
 ﻿use std::time::Instant;
 
+#[derive(Clone, Default)]
 pub struct StdClock {
 }
 
+#[derive(Clone)]
 pub struct StdInstant {
     instant: Instant
 }
 
-impl Clock<StdInstant> for StdClock {
+impl Clock for StdClock {
+    type Instant = StdInstant;
+
     fn now(&self) -> StdInstant {
         StdInstant {
             instant: Instant::now()
@@ -21,24 +25,30 @@ impl ClockInstant for StdInstant {
     }
 }
 
-pub trait Clock<T: ClockInstant> {
-    fn now(&self) -> T;
+pub trait Clock {
+    type Instant: ClockInstant;
+
+    fn now(&self) -> Self::Instant;
 }
 
 pub trait ClockInstant {
     fn difference_millis(&self, other_instant: &Self) -> u128;
 }
 
+#[derive(Clone)]
 pub struct ManualClock {
     pub now_milliseconds: u128
 }
 
-impl Clock<ManualClockInstant> for ManualClock {
+impl Clock for ManualClock {
+    type Instant = ManualClockInstant;
+
     fn now(&self) -> ManualClockInstant {
         ManualClockInstant { milliseconds: self.now_milliseconds }
     }
 }
 
+#[derive(Clone)]
 pub struct ManualClockInstant {
     milliseconds: u128
 }